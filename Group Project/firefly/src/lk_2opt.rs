@@ -2,6 +2,10 @@ use std::time::{Duration, Instant};
 use std::f64;
 use std::io;
 
+// shared with tsp-kth, inline it for a single-file submission
+#[path = "../../src/kdtree.rs"]
+mod kdtree;
+
 
 #[inline]
 fn distance(p1: (f64, f64), p2: (f64, f64)) -> f64 {
@@ -11,37 +15,7 @@ fn distance(p1: (f64, f64), p2: (f64, f64)) -> f64 {
 }
 
 fn initial_tour(points: &[(f64, f64)]) -> Vec<usize> {
-    let num_points = points.len();
-    let mut visited = vec![false; num_points];
-    let mut tour = Vec::with_capacity(num_points);
-
-    tour.push(0);
-    visited[0] = true;
-
-    while tour.len() < num_points {
-        let last_visited = tour[tour.len() - 1];
-        let mut nearest_neighbor = None;
-        let mut min_distance = f64::MAX;
-
-        for (i, &point) in points.iter().enumerate() {
-            if !visited[i] {
-                let dist = distance(points[last_visited], point);
-                if dist < min_distance {
-                    min_distance = dist;
-                    nearest_neighbor = Some(i);
-                }
-            }
-        }
-
-        if let Some(nearest) = nearest_neighbor {
-            tour.push(nearest);
-            visited[nearest] = true;
-        } else {
-            break;
-        }
-    }
-
-    tour
+    kdtree::nearest_neighbor_tour(points)
 }
 
 fn lin_kernighan_heuristic(points: &[(f64, f64)], tour: &mut Vec<usize>, time_limit: Duration) {
@@ -2,6 +2,21 @@ use std::time::SystemTime;
 use std::f64;
 use std::io;
 
+// shared with tsp-kth, inline them for a single-file submission; only the
+// flat matrix is used here
+#[allow(dead_code)]
+#[path = "../../src/metric.rs"]
+mod metric;
+#[allow(dead_code)]
+#[path = "../../src/sparse_graph.rs"]
+mod sparse_graph;
+#[allow(dead_code)]
+#[path = "../../src/distance.rs"]
+mod distance;
+
+use distance::{DistanceOracle, FlatMatrix};
+use metric::Metric;
+
 struct SimpleRng {
     state: u64,
 }
@@ -36,38 +51,19 @@ impl SimpleRng {
 }
 
 #[inline]
-fn distance(p1: (f64, f64), p2: (f64, f64)) -> f64 {
-    let dx = p1.0 - p2.0;
-    let dy = p1.1 - p2.1;
-    (dx * dx + dy * dy).sqrt()
-}
-
-fn compute_distance_matrix(points: &[(f64, f64)]) -> Vec<Vec<f64>> {
-    let mut matrix = vec![vec![0.0; points.len()]; points.len()];
-    for i in 0..points.len() {
-        for j in i+1..points.len() {
-            let dist = distance(points[i], points[j]);
-            matrix[i][j] = dist;
-            matrix[j][i] = dist;
-        }
-    }
-    matrix
-}
-
-#[inline]
-fn distance_from_matrix(matrix: &[Vec<f64>], i: usize, j: usize) -> f64 {
-    matrix[i][j]
+fn distance_from_matrix<D: DistanceOracle>(matrix: &D, i: usize, j: usize) -> f64 {
+    matrix.get_edge(i as i32, j as i32) as f64
 }
 
 #[inline]
-fn total_distance_from_matrix(path: &[usize], matrix: &[Vec<f64>]) -> f64 {
+fn total_distance_from_matrix<D: DistanceOracle>(path: &[usize], matrix: &D) -> f64 {
     let dist: f64 = path.windows(2)
-        .map(|w| distance_from_matrix(&matrix, w[0], w[1]))
+        .map(|w| distance_from_matrix(matrix, w[0], w[1]))
         .sum();
-    dist + distance_from_matrix(&matrix, path[0], path[path.len() - 1])
+    dist + distance_from_matrix(matrix, path[0], path[path.len() - 1])
 }
 
-fn move_firefly(rng: &mut SimpleRng, mut firefly_i: Vec<usize>, firefly_j: &[usize], matrix: &[Vec<f64>], beta0: f64, gamma: f64) -> Vec<usize> {
+fn move_firefly<D: DistanceOracle>(rng: &mut SimpleRng, mut firefly_i: Vec<usize>, firefly_j: &[usize], matrix: &D, beta0: f64, gamma: f64) -> Vec<usize> {
     let firefly_i_distance = total_distance_from_matrix(&firefly_i, matrix);
     let firefly_j_distance = total_distance_from_matrix(firefly_j, matrix);
     let dist_diff = firefly_i_distance - firefly_j_distance;
@@ -92,7 +88,7 @@ fn move_firefly(rng: &mut SimpleRng, mut firefly_i: Vec<usize>, firefly_j: &[usi
     firefly_i
 }
 
-fn three_opt(path: &mut Vec<usize>, matrix: &[Vec<f64>], max_duration: std::time::Duration) {
+fn three_opt<D: DistanceOracle>(path: &mut Vec<usize>, matrix: &D, max_duration: std::time::Duration) {
     let mut improved = true;
     let start_time = SystemTime::now();
 
@@ -170,7 +166,7 @@ fn main() {
     let max_duration = std::time::Duration::new(1, 900_000_000);
     let two_opt_time = std::time::Duration::new(0, 1_200_000_000 / num_fireflies as u32);
 
-    let matrix = compute_distance_matrix(&points);
+    let matrix = FlatMatrix::new(&points, Metric::Euc2d);
 
     for firefly in &mut fireflies {
         three_opt(firefly, &matrix, two_opt_time);
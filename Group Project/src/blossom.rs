@@ -1,85 +1,17 @@
-pub type Vertex = usize;
-pub type Edge = (Vertex, Vertex);
+// Maximum-cardinality matching on unweighted graphs with Edmonds' blossom
+// algorithm. Much cheaper than the weighted mwmatching, so it serves to check
+// whether a sparse graph has a perfect matching at all before the weighted
+// one is solved on it.
 
-
-use std::collections::{HashMap, HashSet};
-use std::iter;
+use std::cmp::Ordering::{Equal, Greater, Less};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::iter::FromIterator;
 
-/// Gets the connected groups in the graph
-pub fn connected_groups(graph: &Graph) -> Vec<Vec<Vertex>> {
-    let mut groups = vec![];
-    let mut seen = HashSet::new();
-    let mut todo = HashSet::new();
-
-    loop {
-        for &v in graph.vertices() {
-            if seen.insert(v) {
-                todo.insert(v);
-                break;
-            }
-        }
-
-        if todo.is_empty() {
-            return groups;
-        }
-
-        while let Some(&v) = todo.iter().next() {
-            todo.remove(&v);
-            for &e in graph.vertices_from(v) {
-                if seen.insert(e) {
-                    todo.insert(e);
-                }
-            }
-        }
-
-        groups.push(seen.iter().cloned().collect());
-    }
-}
-
-
-
-
-pub struct Forest {
-    trees: HashMap<Vertex, Vec<Vertex>>,
-}
-
-impl Forest {
-    pub fn new() -> Forest {
-        Forest {
-            trees: HashMap::new(),
-        }
-    }
-
-    pub fn from_singletons(vertices: &[Vertex]) -> Forest {
-        Forest {
-            trees: vertices.iter().map(|&x| (x, vec![x])).collect(),
-        }
-    }
-
-    pub fn depth(&self, vertex: Vertex) -> Option<usize> {
-        self.trees.get(&vertex).map(|p| p.len())
-    }
-
-    pub fn find_path(&self, vertex: Vertex) -> Option<&[Vertex]> {
-        self.trees.get(&vertex).map(|p| &p[..])
-    }
-
-    pub fn push(&mut self, v: Vertex, p: Vec<Vertex>) {
-        self.trees.insert(v, p);
-    }
-
-    pub fn append(&mut self, other: &mut Forest) {
-        for (v, p) in other.trees.drain() {
-            self.trees.insert(v, p);
-        }
-    }
-}
-
-
-
-
-
+pub type Vertex = usize;
+pub type Edge = (Vertex, Vertex);
+pub type Graph = AnnotatedGraph<()>;
+pub type Weight = f64;
+pub type WeightedGraph<T = Weight> = AnnotatedGraph<T>;
 
 /// Represents a matching in a graph.
 #[derive(Clone, Debug)]
@@ -98,11 +30,6 @@ impl Matching {
         Matching { edges: map }
     }
 
-    /// Returns a value indicating whether the matching is empty.
-    pub fn is_empty(&self) -> bool {
-        self.edges.is_empty()
-    }
-
     /// Returns the number of edges in the graph.
     pub fn len(&self) -> usize {
         self.edges.len() / 2
@@ -117,52 +44,150 @@ impl Matching {
             .collect()
     }
 
-    /// Exports all edges in a vector.
-    pub fn vertices(&self) -> Vec<Vertex> {
-        self.edges.keys().cloned().collect()
-    }
-
     /// Gets the vertex that `vertex` is connected to.
     /// Panics if matching does not contain a vertex `vertex`.
+    #[cfg(test)]
     pub fn partner(&self, vertex: Vertex) -> Vertex {
         self.edges[&vertex]
     }
 
-    /// Creates a contracted matching with edges between `leafs` removed.
-    pub fn contract(&self, leafs: &[Vertex]) -> Matching {
+    /// Adds a matching
+    pub fn add(&self, other: &Matching) -> Matching {
         let mut edges = self.edges.clone();
-        for leaf in leafs {
-            edges.remove(leaf);
+        for (&v, &w) in &other.edges {
+            edges.insert(v, w);
         }
         Matching { edges }
     }
+}
 
-    /// Creates a expanded matching using the augmenting `path`.
-    pub fn augment(&self, path: &[Vertex]) -> Matching {
-        let mut edges = self.edges.clone();
-        for leaf in path {
-            edges.remove(leaf);
-        }
-        for (i, j) in (0..path.len() / 2).map(|i| (2 * i, 2 * i + 1)) {
-            edges.insert(path[i], path[j]);
-            edges.insert(path[j], path[i]);
+const NONE: usize = usize::MAX;
+
+/// State of the search for augmenting paths from one exposed vertex at a
+/// time, on vertices numbered 0 .. n. Blossoms are not contracted in the
+/// graph; every vertex only records the base of the outermost blossom it
+/// lies in.
+struct Search {
+    adjacent: Vec<Vec<usize>>,
+    mate: Vec<usize>,
+    // the vertex an odd vertex of the tree was reached from
+    parent: Vec<usize>,
+    base: Vec<usize>,
+    // even vertices of the tree, the ones the search goes on from
+    even: Vec<bool>,
+    in_blossom: Vec<bool>,
+    queue: VecDeque<usize>,
+}
+
+impl Search {
+    fn new(adjacent: Vec<Vec<usize>>) -> Search {
+        let n = adjacent.len();
+        Search {
+            adjacent,
+            mate: vec![NONE; n],
+            parent: vec![NONE; n],
+            base: (0..n).collect(),
+            even: vec![false; n],
+            in_blossom: vec![false; n],
+            queue: VecDeque::new(),
         }
-        Matching { edges }
     }
 
-    /// Adds a matching
-    pub fn add(&self, other: &Matching) -> Matching {
-        let mut edges = self.edges.clone();
-        for (&v, &w) in &other.edges {
-            edges.insert(v, w);
+    /// Returns the exposed vertex an augmenting path from root ends at.
+    fn find_augmenting_path(&mut self, root: usize) -> Option<usize> {
+        let n = self.adjacent.len();
+        self.parent.iter_mut().for_each(|p| *p = NONE);
+        self.even.iter_mut().for_each(|e| *e = false);
+        for v in 0..n {
+            self.base[v] = v;
         }
-        Matching { edges }
+        self.queue.clear();
+        self.even[root] = true;
+        self.queue.push_back(root);
+
+        while let Some(v) = self.queue.pop_front() {
+            for i in 0..self.adjacent[v].len() {
+                let w = self.adjacent[v][i];
+                if self.base[v] == self.base[w] || self.mate[v] == w {
+                    continue;
+                }
+                if w == root || (self.mate[w] != NONE && self.parent[self.mate[w]] != NONE) {
+                    // w is even as well: an odd cycle, contract it
+                    self.contract(v, w);
+                } else if self.parent[w] == NONE {
+                    self.parent[w] = v;
+                    if self.mate[w] == NONE {
+                        return Some(w);
+                    }
+                    let x = self.mate[w];
+                    self.even[x] = true;
+                    self.queue.push_back(x);
+                }
+            }
+        }
+        None
     }
-}
 
+    /// The base of the smallest blossom containing the even vertices v and w.
+    fn common_base(&self, mut v: usize, mut w: usize) -> usize {
+        let mut on_path = vec![false; self.adjacent.len()];
+        loop {
+            v = self.base[v];
+            on_path[v] = true;
+            if self.mate[v] == NONE {
+                break;
+            }
+            v = self.parent[self.mate[v]];
+        }
+        loop {
+            w = self.base[w];
+            if on_path[w] {
+                return w;
+            }
+            w = self.parent[self.mate[w]];
+        }
+    }
 
+    fn contract(&mut self, v: usize, w: usize) {
+        let base = self.common_base(v, w);
+        self.in_blossom.iter_mut().for_each(|b| *b = false);
+        self.mark_path(v, base, w);
+        self.mark_path(w, base, v);
+        for u in 0..self.adjacent.len() {
+            if self.in_blossom[self.base[u]] {
+                self.base[u] = base;
+                // the odd vertices of the blossom become even
+                if !self.even[u] {
+                    self.even[u] = true;
+                    self.queue.push_back(u);
+                }
+            }
+        }
+    }
 
+    // Marks the blossoms from v up to base and points the parents of the
+    // odd vertices on the way back along the cycle, through child.
+    fn mark_path(&mut self, mut v: usize, base: usize, mut child: usize) {
+        while self.base[v] != base {
+            self.in_blossom[self.base[v]] = true;
+            self.in_blossom[self.base[self.mate[v]]] = true;
+            self.parent[v] = child;
+            child = self.mate[v];
+            v = self.parent[self.mate[v]];
+        }
+    }
 
+    /// Flips the matched and unmatched edges on the path to the exposed end.
+    fn augment(&mut self, mut end: usize) {
+        while end != NONE {
+            let v = self.parent[end];
+            let next = self.mate[v];
+            self.mate[end] = v;
+            self.mate[v] = end;
+            end = next;
+        }
+    }
+}
 
 /// A graph with annotations on edges
 #[derive(Clone, Debug)]
@@ -191,8 +216,8 @@ impl<Annotation> AnnotatedGraph<Annotation>
     ///
     /// # Example
     ///
-    /// ```
-    /// use blossom::graph::AnnotatedGraph;
+    /// ```ignore
+    /// use crate::blossom::AnnotatedGraph;
     /// let graph = AnnotatedGraph::new([
     ///   (0, (vec![1, 2], vec![0.1, 0.4])),
     ///   (2, (vec![0], vec![0.4])),
@@ -217,6 +242,7 @@ impl<Annotation> AnnotatedGraph<Annotation>
     }
 
     /// Gets the vertices in the graph
+    #[cfg(test)]
     pub fn vertices(&self) -> &[Vertex] {
         &self.vertices
     }
@@ -231,16 +257,17 @@ impl<Annotation> AnnotatedGraph<Annotation>
         (&self.edges[&vertex].0, &self.edges[&vertex].1)
     }
 
+    #[cfg(test)]
     fn contract(&self, root: Vertex, leafs: &[Vertex]) -> Graph {
         let with_meta = |vertices: Vec<Vertex>| {
             let len = vertices.len();
-            (vertices, iter::repeat(()).take(len).collect::<Vec<_>>())
+            (vertices, vec![(); len])
         };
         let mut edges: HashMap<Vertex, (Vec<Vertex>, Vec<()>)> = self
             .edges
             .iter()
             .filter(|e| *e.0 != root && !leafs.contains(e.0))
-            .map(|(&v, &(ref w, _))| {
+            .map(|(&v, (w, _))| {
                 let mut has_root = false;
                 let mut partners = Vec::with_capacity(w.len());
                 for &p in w {
@@ -258,7 +285,7 @@ impl<Annotation> AnnotatedGraph<Annotation>
             .edges
             .iter()
             .filter(|e| *e.0 == root || leafs.contains(e.0))
-            .flat_map(|(_, &(ref w, _))| w.iter().filter(|&p| *p != root && !leafs.contains(p)))
+            .flat_map(|(_, (w, _))| w.iter().filter(|&p| *p != root && !leafs.contains(p)))
             .cloned()
             .collect();
         root_partners.sort_unstable();
@@ -283,7 +310,7 @@ impl<Annotation> AnnotatedGraph<Annotation>
         let mut min = self.len();
         let mut vertex = 0;
         let mut edges = &vec![];
-        for (&v, &(ref e, _)) in &self.edges {
+        for (&v, (e, _)) in &self.edges {
             if e.is_empty() {
                 return Some(self.split(&[v].iter().cloned().collect()));
             }
@@ -326,7 +353,7 @@ impl<Annotation> AnnotatedGraph<Annotation>
             self.edges
                 .iter()
                 .filter(|t| predicate(t.0))
-                .map(|(v, &(ref e0, ref m0))| {
+                .map(|(v, (e0, m0))| {
                     let (e1, m1): (Vec<_>, Vec<_>) =
                         e0.iter().zip(m0.iter()).filter(|t| predicate(t.0)).unzip();
                     (*v, (e1, m1))
@@ -343,7 +370,7 @@ impl<Annotation> AnnotatedGraph<Annotation>
         Self::new(
             self.edges
                 .iter()
-                .map(|(v, &(ref e0, ref m0))| {
+                .map(|(v, (e0, m0))| {
                     let (e1, m1): (Vec<_>, Vec<_>) = e0
                         .iter()
                         .zip(m0.iter())
@@ -373,16 +400,6 @@ impl<Annotation> AnnotatedGraph<Annotation>
         Matching::new(&matches[..])
     }
 
-    fn exposed(&self, matching: &Matching) -> Vec<Vertex> {
-        let mut matched = matching.vertices();
-        matched.sort_unstable();
-        self.vertices()
-            .iter()
-            .filter(|x| matched.binary_search(x).is_err())
-            .cloned()
-            .collect()
-    }
-
     /// Determines a maximum matching in the current graph.
     ///
     /// Note: the (undirected) edges may be represented in
@@ -390,8 +407,8 @@ impl<Annotation> AnnotatedGraph<Annotation>
     ///
     /// # Example
     ///
-    /// ```
-    /// use blossom::Graph;
+    /// ```ignore
+    /// use crate::blossom::Graph;
     /// let graph: Graph = [
     ///   (0, vec![1, 2, 3]),
     ///   (1, vec![0, 2]),
@@ -404,18 +421,34 @@ impl<Annotation> AnnotatedGraph<Annotation>
     /// assert!(matching_edges.contains(&(0, 3)) || matching_edges.contains(&(3, 0)));
     /// ```
     pub fn maximum_matching(&self) -> Matching {
-        let mut matching = self.initial_matching();
-        loop {
-            let option = self.find_augmenting_path(&matching);
-            if option.is_none() {
-                break;
-            }
-            matching = matching.augment(&option.unwrap());
-            if 2 * matching.len() == self.len() {
-                break;
+        // the search works on the vertices numbered 0 .. len
+        let mut vertices = self.vertices.clone();
+        vertices.sort_unstable();
+        let index: HashMap<Vertex, usize> = vertices.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+        let adjacent: Vec<Vec<usize>> = vertices
+            .iter()
+            .map(|&v| self.vertices_from(v).iter().map(|w| index[w]).collect())
+            .collect();
+
+        let mut search = Search::new(adjacent);
+        for (v, w) in self.initial_matching().edges() {
+            search.mate[index[&v]] = index[&w];
+            search.mate[index[&w]] = index[&v];
+        }
+        // A vertex without augmenting path now does not get one later,
+        // so every exposed vertex needs only one search.
+        for root in 0..vertices.len() {
+            if search.mate[root] == NONE {
+                if let Some(end) = search.find_augmenting_path(root) {
+                    search.augment(end);
+                }
             }
         }
-        matching
+        let edges: Vec<Edge> = (0..vertices.len())
+            .filter(|&v| search.mate[v] != NONE && v < search.mate[v])
+            .map(|v| (vertices[v], vertices[search.mate[v]]))
+            .collect();
+        Matching::new(&edges)
     }
 
     /// Determines a full matching in the current graph.
@@ -461,118 +494,6 @@ impl<Annotation> AnnotatedGraph<Annotation>
             self.filter_vertices(|v| !vertices.contains(v)),
         ]
     }
-
-    fn find_augmenting_path(&self, m: &Matching) -> Option<Vec<Vertex>> {
-        let mut vertices_todo = self.exposed(m);
-        let mut f = Forest::from_singletons(&vertices_todo);
-        let mut vertices_done = HashSet::new();
-        let mut temp = Forest::new();
-        while let Some(v) = vertices_todo.pop() {
-            let todo: Vec<Vertex> = self
-                .vertices_from(v)
-                .iter()
-                .filter(|&w| !vertices_done.contains(w))
-                .cloned()
-                .collect();
-            for &w in &todo {
-                if let Some(wlen) = f.depth(w) {
-                    if wlen % 2 == 1 {
-                        let root: Vertex;
-                        let vleafs: Vec<Vertex>;
-                        let wleafs: Vec<Vertex>;
-                        let leafs: Vec<Vertex>;
-                        {
-                            let vpath = f.find_path(v).unwrap();
-                            let wpath = f.find_path(w).unwrap();
-                            if vpath[0] != wpath[0] {
-                                return Some(
-                                    vpath.iter().chain(wpath.iter().rev()).cloned().collect(),
-                                );
-                            }
-
-                            let common_len = vpath
-                                .iter()
-                                .zip(wpath.iter())
-                                .take_while(|&(&v, &w)| v == w)
-                                .count();
-                            root = vpath[common_len - 1];
-                            vleafs = vpath.iter().skip(common_len).cloned().collect();
-                            wleafs = wpath.iter().skip(common_len).cloned().collect();
-                            leafs = vleafs.iter().chain(wleafs.iter()).cloned().collect();
-                        }
-                        let gc = self.contract(root, &leafs);
-                        let mc = m.contract(&leafs);
-                        return gc
-                            .find_augmenting_path(&mc)
-                            .map(|path| self.lift(path, root, &vleafs, &wleafs));
-                    }
-                } else {
-                    let x = m.partner(w);
-                    let mut path: Vec<Vertex> = f.find_path(v).unwrap().to_vec();
-                    path.push(w);
-                    temp.push(w, path.clone());
-                    path.push(x);
-                    temp.push(x, path);
-                    vertices_todo.push(x);
-                }
-                f.append(&mut temp);
-            }
-            vertices_done.insert(v);
-        }
-        None
-    }
-
-    fn lift(
-        &self,
-        mut path: Vec<Vertex>,
-        root: Vertex,
-        vleafs: &[Vertex],
-        wleafs: &[Vertex],
-    ) -> Vec<Vertex> {
-        if let Some(root_position) = path.iter().position(|&x| x == root) {
-            let after = root_position % 2 == 0;
-            let match_position = if after {
-                root_position + 1
-            } else {
-                root_position - 1
-            };
-            let match_vertex = path[match_position];
-            let match_partners = self.vertices_from(match_vertex);
-            if match_partners.iter().any(|&w| w == root) {
-                return path;
-            }
-            let mut insert_position = root_position + (if after { 1 } else { 0 });
-            for (l, &leafs) in (&[vleafs, wleafs]).iter().enumerate() {
-                if let Some(n) = leafs.iter().position(|x| match_partners.contains(x)) {
-                    let from_root: Vec<Vertex> = if n % 2 == 0 {
-                        (if l == 0 { wleafs } else { vleafs })
-                            .iter()
-                            .chain(leafs.iter().skip(n).rev())
-                            .cloned()
-                            .collect()
-                    } else {
-                        leafs.iter().take(n + 1).cloned().collect()
-                    };
-                    for &leaf in &from_root {
-                        path.insert(insert_position, leaf);
-                        if after {
-                            insert_position += 1;
-                        }
-                    }
-                    return path;
-                }
-            }
-
-            panic!(
-                "Lift failed; path length = {}, branch lengths = {}/{}",
-                path.len(),
-                vleafs.len(),
-                wleafs.len()
-            )
-        } else {
-            path
-        }
-    }
 }
 
 impl<Annotation> FromIterator<(Vertex, (Vec<Vertex>, Vec<Annotation>))>
@@ -599,90 +520,13 @@ for AnnotatedGraph<Annotation>
     }
 }
 
-#[test]
-fn contract() {
-    let g: Graph = [(0, vec![1, 2]), (1, vec![0, 2]), (2, vec![0, 1])]
-        .iter()
-        .collect();
-    let gc = g.contract(1, &vec![2]);
-    assert_eq!(2, gc.vertices().len());
-    assert_eq!(1, gc.vertices_from(0).len());
-    assert_eq!(1, gc.vertices_from(0)[0]);
-    assert_eq!(1, gc.vertices_from(1).len());
-    assert_eq!(0, gc.vertices_from(1)[0]);
-}
-
-#[test]
-fn contract_take_edge_from_leaf() {
-    let g: Graph = [(0, vec![1]), (1, vec![0, 2]), (2, vec![1])]
-        .iter()
-        .collect();
-    let gc = g.contract(2, &vec![1]);
-    assert_eq!(2, gc.vertices().len());
-    assert_eq!(1, gc.vertices_from(0).len());
-    assert_eq!(2, gc.vertices_from(0)[0]);
-    assert_eq!(1, gc.vertices_from(2).len());
-    assert_eq!(0, gc.vertices_from(2)[0]);
-}
-
-#[test]
-fn find_match_one() {
-    let g: Graph = [(0, vec![1]), (1, vec![0])].iter().collect();
-    let m = g.maximum_matching();
-    verify_matching(&g, &m, 1);
-}
-
-#[test]
-fn find_match_two() {
-    let g: Graph = [(0, vec![1]), (1, vec![0]), (2, vec![3]), (3, vec![2])]
-        .iter()
-        .collect();
-    let m = g.maximum_matching();
-    verify_matching(&g, &m, 2);
-}
-
-#[test]
-fn find_match_four() {
-    let g: Graph = [
-        (0, vec![1, 4]),
-        (1, vec![0, 3]),
-        (2, vec![3, 7]),
-        (3, vec![1, 2, 5]),
-        (4, vec![0, 5]),
-        (5, vec![3, 4, 6, 7]),
-        (6, vec![5]),
-        (7, vec![2, 5]),
-    ]
-        .iter()
-        .collect();
-    let m = g.maximum_matching();
-    verify_matching(&g, &m, 4);
-}
-
-#[cfg(test)]
-fn verify_matching(g: &Graph, matching: &Matching, minimum: usize) {
-    assert!(matching.len() >= minimum);
-    assert!(matching.len() * 2 <= g.len());
-    debug_assert!(matching
-        .edges()
-        .iter()
-        .all(|&(a, b)| g.vertices_from(a).iter().any(|&e| e == b)));
-}
-
-
-
-
-
-
-pub type Graph = AnnotatedGraph<()>;
-
 impl FromIterator<(Vertex, Vec<Vertex>)> for Graph {
     fn from_iter<I: IntoIterator<Item = (Vertex, Vec<Vertex>)>>(iter: I) -> Self {
         Self::new(
             iter.into_iter()
                 .map(|(v, e)| {
                     let len = e.len();
-                    (v, (e, iter::repeat(()).take(len).collect()))
+                    (v, (e, vec![(); len]))
                 })
                 .collect(),
         )
@@ -695,74 +539,228 @@ impl<'a> FromIterator<&'a (Vertex, Vec<Vertex>)> for Graph {
     }
 }
 
-
-use std::cmp::Ordering::{Equal, Greater, Less};
-use std::f64;
-
-
-
-pub type Weight = f64;
-pub type WeightedGraph<T = Weight> = AnnotatedGraph<T>;
+impl Graph {
+    /// Returns the graph on the vertices 0 .. num_vertices with the given
+    /// edges, each listed once. Vertices without edges are kept.
+    pub fn from_edges(num_vertices: usize, edges: &[Edge]) -> Graph {
+        let mut adjacent = vec![vec![]; num_vertices];
+        for &(v, w) in edges {
+            adjacent[v].push(w);
+            adjacent[w].push(v);
+        }
+        adjacent.into_iter().enumerate().collect()
+    }
+}
 
 impl<T> WeightedGraph<T>
     where
         T: PartialEq + PartialOrd + Copy,
 {
-    /// Returns a full matching with iteratively the maximum minimal weight edge.
+    /// Returns a full matching whose lightest edge is as heavy as possible,
+    /// or None if there is no full matching.
     pub fn maximin_matching(&self) -> Option<Matching> {
         if self.is_empty() {
             return Some(Matching::new(&[]));
         }
 
         let cmp = |x: &T, y: &T| x.partial_cmp(y).unwrap_or(Equal);
-        let cmp_rev = |x: &T, y: &T| cmp(y, x);
-        self.full_matching()?;
+        let mut matching = self.full_matching()?;
 
+        // every vertex is matched by one of its edges, so no matching does
+        // better than the lightest of the heaviest edges of the vertices
         let min_max_value = self
-            .vertices()
+            .vertices
             .iter()
             .map(|&v| self.edges_from(v).1.iter().cloned().max_by(cmp).unwrap())
             .min_by(cmp)
             .unwrap();
         let mut values: Vec<_> = self
-            .vertices()
+            .vertices
             .iter()
             .flat_map(|&vertex| self.edges_from(vertex).1)
             .filter(|&w| cmp(w, &min_max_value) != Greater)
             .cloned()
             .collect();
-        values.sort_by(cmp_rev);
-
-        for value in values {
-            let limited = self.limit(value);
-            if let Some(matching) = limited.full_matching() {
-                let mut edges: Vec<Edge> = matching
-                    .edges()
-                    .into_iter()
-                    .filter(|edge| limited.weight(edge).eq(&value))
-                    .collect();
-                edges.sort_by_key(|&(v, w)| limited.vertices_from(v).min(limited.vertices_from(w)));
-                let sub_matching = limited
-                    .filter_vertices(|&v| v != edges[0].0 && v != edges[0].1)
-                    .maximin_matching()
-                    .unwrap();
-                return Some(sub_matching.add(&Matching::new(&edges[0..1])));
+        values.sort_by(cmp);
+        values.dedup();
+
+        // the limited graphs lose edges as the value grows, the first one
+        // keeps all of them and has the matching found above
+        let (mut low, mut high) = (0, values.len() - 1);
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            match self.limit(values[mid]).full_matching() {
+                Some(limited) => {
+                    matching = limited;
+                    low = mid;
+                }
+                None => high = mid - 1,
             }
         }
-
-        None
+        Some(matching)
     }
 
     // Clone graph removing edges with weight less than given limit
     pub fn limit(&self, weight: T) -> WeightedGraph<T> {
         self.filter_edges(|_, _, &w| w.partial_cmp(&weight) != Some(Less))
     }
+}
 
-    fn weight(&self, edge: &Edge) -> T {
-        let (v, w) = self.edges_from(edge.0);
-        *v.iter().zip(w.iter()).find(|t| *t.0 == edge.1).unwrap().1
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mwmatching;
+    use crate::utils::SimpleRng;
+
+    #[test]
+    fn contract() {
+        let g: Graph = [(0, vec![1, 2]), (1, vec![0, 2]), (2, vec![0, 1])]
+            .iter()
+            .collect();
+        let gc = g.contract(1, &[2]);
+        assert_eq!(2, gc.vertices().len());
+        assert_eq!(1, gc.vertices_from(0).len());
+        assert_eq!(1, gc.vertices_from(0)[0]);
+        assert_eq!(1, gc.vertices_from(1).len());
+        assert_eq!(0, gc.vertices_from(1)[0]);
     }
-}
 
+    #[test]
+    fn contract_take_edge_from_leaf() {
+        let g: Graph = [(0, vec![1]), (1, vec![0, 2]), (2, vec![1])]
+            .iter()
+            .collect();
+        let gc = g.contract(2, &[1]);
+        assert_eq!(2, gc.vertices().len());
+        assert_eq!(1, gc.vertices_from(0).len());
+        assert_eq!(2, gc.vertices_from(0)[0]);
+        assert_eq!(1, gc.vertices_from(2).len());
+        assert_eq!(0, gc.vertices_from(2)[0]);
+    }
 
+    #[test]
+    fn find_match_one() {
+        let g: Graph = [(0, vec![1]), (1, vec![0])].iter().collect();
+        let m = g.maximum_matching();
+        verify_matching(&g, &m, 1);
+    }
+
+    #[test]
+    fn find_match_two() {
+        let g: Graph = [(0, vec![1]), (1, vec![0]), (2, vec![3]), (3, vec![2])]
+            .iter()
+            .collect();
+        let m = g.maximum_matching();
+        verify_matching(&g, &m, 2);
+    }
+
+    #[test]
+    fn find_match_four() {
+        let g: Graph = [
+            (0, vec![1, 4]),
+            (1, vec![0, 3]),
+            (2, vec![3, 7]),
+            (3, vec![1, 2, 5]),
+            (4, vec![0, 5]),
+            (5, vec![3, 4, 6, 7]),
+            (6, vec![5]),
+            (7, vec![2, 5]),
+        ]
+            .iter()
+            .collect();
+        let m = g.maximum_matching();
+        verify_matching(&g, &m, 4);
+    }
+
+    fn verify_matching(g: &Graph, matching: &Matching, minimum: usize) {
+        assert!(matching.len() >= minimum);
+        assert!(matching.len() * 2 <= g.len());
+        debug_assert!(matching
+            .edges()
+            .iter()
+            .all(|&(a, b)| g.vertices_from(a).contains(&b)));
+    }
+
+    #[test]
+    fn cardinality_matches_mwmatching() {
+        let mut rng = SimpleRng::new(5);
+        for _ in 0..200 {
+            let n = rng.gen_range(2, 30);
+            let mut edges = vec![];
+            for v in 0..n {
+                for w in v + 1..n {
+                    if rng.gen_range(0, n) < 3 {
+                        edges.push((v, w));
+                    }
+                }
+            }
+            let m = Graph::from_edges(n, &edges).maximum_matching();
+            verify_matching(&Graph::from_edges(n, &edges), &m, 0);
+            let weighted: Vec<(Vertex, Vertex, i32)> = edges.iter().map(|&(v, w)| (v, w, 1)).collect();
+            let mate = mwmatching::Matching::new(weighted).max_cardinality().solve();
+            let size = mate.iter().filter(|&&w| w != mwmatching::SENTINEL).count() / 2;
+            assert_eq!(m.len(), size);
+            assert_eq!(Graph::from_edges(n, &edges).full_matching().is_some(), 2 * size == n);
+        }
+    }
 
+    #[test]
+    fn full_matching_needs_even_components() {
+        // two triangles, joined by an edge or not
+        let mut edges = vec![(0, 1), (1, 2), (2, 0), (3, 4), (4, 5), (5, 3)];
+        assert!(Graph::from_edges(6, &edges).full_matching().is_none());
+        assert_eq!(Graph::from_edges(6, &edges).maximum_matching().len(), 2);
+        edges.push((2, 3));
+        let m = Graph::from_edges(6, &edges).full_matching().unwrap();
+        assert_eq!(m.len(), 3);
+        assert_eq!(m.partner(2), 3);
+        // an isolated vertex
+        assert!(Graph::from_edges(4, &[(0, 1), (1, 2)]).full_matching().is_none());
+    }
+
+    // the largest lightest edge of all full matchings, trying all of them
+    fn brute_force_maximin(weights: &HashMap<Edge, i32>, free: &mut Vec<Vertex>) -> Option<i32> {
+        let Some(v) = free.pop() else { return Some(i32::MAX) };
+        let mut best = None;
+        for i in 0..free.len() {
+            if let Some(&wt) = weights.get(&(v.min(free[i]), v.max(free[i]))) {
+                let w = free.remove(i);
+                if let Some(rest) = brute_force_maximin(weights, free) {
+                    best = best.max(Some(rest.min(wt)));
+                }
+                free.insert(i, w);
+            }
+        }
+        free.push(v);
+        best
+    }
+
+    #[test]
+    fn maximin_matching_is_optimal() {
+        let mut rng = SimpleRng::new(6);
+        for _ in 0..200 {
+            let n = 2 * rng.gen_range(1, 5);
+            let mut weights = HashMap::new();
+            let mut adjacent: Vec<(Vec<Vertex>, Vec<i32>)> = vec![(vec![], vec![]); n];
+            for v in 0..n {
+                for w in v + 1..n {
+                    if rng.gen_range(0, 3) > 0 {
+                        let wt = rng.gen_range(0, 20) as i32;
+                        weights.insert((v, w), wt);
+                        adjacent[v].0.push(w);
+                        adjacent[v].1.push(wt);
+                        adjacent[w].0.push(v);
+                        adjacent[w].1.push(wt);
+                    }
+                }
+            }
+            let graph: WeightedGraph<i32> = adjacent.into_iter().enumerate().collect();
+            let expected = brute_force_maximin(&weights, &mut (0..n).collect());
+            let found = graph.maximin_matching().map(|m| {
+                assert_eq!(2 * m.len(), n);
+                m.edges().iter().map(|&(v, w)| weights[&(v.min(w), v.max(w))]).min().unwrap()
+            });
+            assert_eq!(found, expected);
+        }
+    }
+}
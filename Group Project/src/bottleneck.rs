@@ -0,0 +1,427 @@
+// Bottleneck TSP: the tour whose longest edge is as short as possible.
+//
+// The optimal bottleneck is one of the edge lengths, and a threshold t is
+// feasible if the graph of the edges no longer than t has a Hamiltonian
+// cycle. Deciding that is NP-hard, but three necessary conditions are cheap:
+// every node has two such edges, the graph is biconnected, and it has a
+// perfect matching (every other edge of the cycle; for odd n with one node
+// left out). The smallest threshold passing them is a lower bound. Between
+// it and the longest edge of the starting tour, thresholds are binary
+// searched with a local search that penalizes the edges above them.
+//
+// The conditions only look at the edges to the k nearest neighbors of every
+// node, which include all edges shorter than the k-th neighbor of the node
+// with the closest one; below that length they are exact. Above it only
+// 2-edge-connectivity is tested, a weaker condition than biconnectivity, on
+// two spanning forests that decide it for every threshold: the minimum one
+// and the minimum one of the other edges. It still sees that clusters far
+// apart need two edges between them. The forests take O(n^2) time but only
+// O(n) memory; if the time runs out before them, the bound stops at the
+// length the lists are complete for.
+
+use std::cmp::Reverse;
+use std::time::Instant;
+use crate::blossom::WeightedGraph;
+use crate::distance::DistanceOracle;
+use crate::iterated_local_search::iterated_local_search;
+use crate::neighbors::CandidateList;
+use crate::utils::{or_opt, two_opt};
+
+// how much more than its length an edge above the threshold costs per unit
+// of excess; large enough that the local search gets rid of it first
+const PENALTY: i32 = 100;
+// seed of the kicks of the iterated local search
+const SEED: u64 = 54321;
+// nearest neighbors of every node the lower bound looks at, whatever the
+// candidates of the local search are
+pub const BOUND_NEIGHBORS: usize = 32;
+
+pub struct BottleneckTour {
+    pub tour: Vec<i32>,
+    // longest edge of the tour
+    pub bottleneck: i32,
+    // no tour has a shorter longest edge
+    pub lower_bound: i32,
+}
+
+// Edges above the threshold cost extra in proportion to how far they are
+// above it. Still increasing in the length, so the candidate lists sorted by
+// length stay sorted.
+struct Penalized<'a, D> {
+    graph: &'a D,
+    threshold: i32,
+}
+
+impl<D: DistanceOracle> DistanceOracle for Penalized<'_, D> {
+    fn num_nodes(&self) -> usize {
+        self.graph.num_nodes()
+    }
+
+    fn get_edge(&self, x: i32, y: i32) -> i32 {
+        let length = self.graph.get_edge(x, y);
+        if length <= self.threshold {
+            length
+        } else {
+            length.saturating_add((length - self.threshold).saturating_mul(PENALTY))
+        }
+    }
+}
+
+pub fn bottleneck<D: DistanceOracle>(graph: &D, tour: &[i32]) -> i32 {
+    if tour.len() < 2 {
+        return 0;
+    }
+    (0..tour.len()).map(|i| graph.get_edge(tour[i], tour[(i + 1) % tour.len()])).max().unwrap()
+}
+
+// Improves the longest edge of the tour, starting from its current one.
+// nearest starts every list with the BOUND_NEIGHBORS nearest nodes, sorted
+// by distance, like CandidateList::new; more nodes may follow.
+pub fn bottleneck_tour<D: DistanceOracle>(graph: &D, candidates: &CandidateList, nearest: &CandidateList, tour: Vec<i32>, start_time: Instant, max_processing_ms: u128) -> BottleneckTour {
+    let n = graph.num_nodes();
+    let upper = bottleneck(graph, &tour);
+    if n < 4 {
+        return BottleneckTour { tour, bottleneck: upper, lower_bound: upper };
+    }
+
+    // the lists hold every edge shorter than complete_below
+    let complete_below = (0..n as i32)
+        .map(|x| match nearest.get(x) {
+            list if list.len() >= n - 1 => i32::MAX,
+            list => graph.get_edge(x, list[BOUND_NEIGHBORS - 1]),
+        })
+        .min()
+        .unwrap();
+    let mut edges: Vec<(i32, usize, usize)> = (0..n as i32)
+        .flat_map(|x| nearest.get(x).iter().map(move |&y| (x.min(y), x.max(y))))
+        .map(|(x, y)| (graph.get_edge(x, y), x as usize, y as usize))
+        .collect();
+    let forests = spanning_forests(graph, start_time, max_processing_ms);
+    if let Some(forests) = &forests {
+        edges.extend_from_slice(forests);
+    }
+    edges.sort_unstable();
+    edges.dedup();
+
+    // every node needs two edges, the lists are sorted by distance
+    let degree_bound = (0..n as i32).map(|x| graph.get_edge(x, nearest.get(x)[1])).max().unwrap();
+    // the matching whose longest edge is shortest, among the edges the lists
+    // are complete for; for odd n without node 0
+    let mut matching_bound = degree_bound;
+    if degree_bound < complete_below && start_time.elapsed().as_millis() < max_processing_ms {
+        let mut adjacent = vec![(Vec::new(), Vec::new()); n];
+        for &(length, x, y) in edges.iter().take_while(|e| e.0 < complete_below) {
+            adjacent[x].0.push(y);
+            adjacent[x].1.push(Reverse(length));
+            adjacent[y].0.push(x);
+            adjacent[y].1.push(Reverse(length));
+        }
+        let mut weighted: WeightedGraph<Reverse<i32>> = adjacent.into_iter().enumerate().collect();
+        if n % 2 == 1 {
+            weighted = weighted.filter_vertices(|&v| v != 0);
+        }
+        // usually the edges up to the degree bound have one already
+        if weighted.limit(Reverse(degree_bound)).full_matching().is_none() {
+            matching_bound = match weighted.maximin_matching() {
+                Some(matching) => matching.edges().iter().map(|&(x, y)| graph.get_edge(x as i32, y as i32)).max().unwrap(),
+                None => complete_below,
+            };
+        }
+    }
+
+    // Both connectivity conditions are monotone in the threshold and the
+    // stronger one holding below complete_below implies the weaker one
+    // above, so the first threshold passing is binary searched; if the time
+    // runs out, the bound is the first one not known to fail. The tour
+    // passes upper, without the forests nothing from complete_below on is
+    // decided.
+    let start = degree_bound.max(matching_bound);
+    let decided = if forests.is_some() { upper } else { complete_below.min(upper) };
+    let mut bound_lengths: Vec<i32> = edges.iter().map(|e| e.0).filter(|&length| start <= length && length < decided).collect();
+    bound_lengths.dedup();
+    let mut failed = 0;
+    let mut passed = bound_lengths.len();
+    while failed < passed && start_time.elapsed().as_millis() < max_processing_ms {
+        let mid = (failed + passed) / 2;
+        let threshold = bound_lengths[mid];
+        let count = edges.partition_point(|e| e.0 <= threshold);
+        let connected = if threshold < complete_below {
+            biconnected(n, &edges[..count])
+        } else {
+            // the forests alone decide it
+            let forests = forests.as_ref().unwrap();
+            let count = forests.partition_point(|e| e.0 <= threshold);
+            bridgeless(n, &forests[..count])
+        };
+        if connected {
+            passed = mid;
+        } else {
+            failed = mid + 1;
+        }
+    }
+    let lower_bound = bound_lengths.get(failed).copied().unwrap_or(decided).max(start);
+
+    // the thresholds the local search tries: the lengths of the edges it
+    // knows, the tour's longest one included
+    let mut lengths: Vec<i32> = edges.iter().map(|e| e.0)
+        .chain((0..n as i32).flat_map(|x| candidates.get(x).iter().map(move |&y| graph.get_edge(x, y))))
+        .chain((0..n).map(|i| graph.get_edge(tour[i], tour[(i + 1) % n])))
+        .filter(|&length| lower_bound <= length && length <= upper)
+        .collect();
+    lengths.sort_unstable();
+    lengths.dedup();
+    let mut low_index = 0;
+
+    // search a tour within every threshold tried; the time is split evenly
+    // between the steps the binary search has left
+    let mut best = tour;
+    let mut high = upper;
+    let mut high_index = lengths.len() - 1;
+    while low_index < high_index {
+        let elapsed = start_time.elapsed().as_millis();
+        if elapsed >= max_processing_ms {
+            break;
+        }
+        let steps = (usize::BITS - (high_index - low_index).leading_zeros()) as u128;
+        let budget = (max_processing_ms - elapsed) / steps.max(1);
+        let mid = (low_index + high_index) / 2;
+        let threshold = lengths[mid];
+
+        let step_start = Instant::now();
+        let penalized = Penalized { graph, threshold };
+        let mut tour = or_opt(&penalized, candidates, two_opt(&penalized, candidates, best.clone(), step_start, budget), step_start, budget);
+        if bottleneck(graph, &tour) > threshold {
+            tour = iterated_local_search(&penalized, candidates, tour, SEED, step_start, budget);
+        }
+        let found = bottleneck(graph, &tour);
+        if found <= threshold {
+            best = tour;
+            high = found;
+            high_index = lengths.partition_point(|&length| length < found);
+        } else {
+            low_index = mid + 1;
+        }
+    }
+    BottleneckTour { tour: best, bottleneck: high, lower_bound }
+}
+
+// The minimum spanning tree and the minimum spanning forest of the other
+// edges, sorted; None if the time runs out.
+fn spanning_forests<D: DistanceOracle>(graph: &D, start_time: Instant, max_processing_ms: u128) -> Option<Vec<(i32, usize, usize)>> {
+    let n = graph.num_nodes();
+    let mut tree = vec![Vec::new(); n];
+    let mut forests = spanning_forest(graph, &tree, start_time, max_processing_ms)?;
+    for &(_, x, y) in &forests {
+        tree[x].push(y);
+        tree[y].push(x);
+    }
+    forests.extend(spanning_forest(graph, &tree, start_time, max_processing_ms)?);
+    forests.sort_unstable();
+    Some(forests)
+}
+
+// Minimum spanning forest of the edges not in the given adjacency lists,
+// with Prim's algorithm on all distances: O(n^2) time, O(n) memory.
+fn spanning_forest<D: DistanceOracle>(graph: &D, without: &[Vec<usize>], start_time: Instant, max_processing_ms: u128) -> Option<Vec<(i32, usize, usize)>> {
+    let n = graph.num_nodes();
+    let mut forest = Vec::with_capacity(n);
+    let mut in_forest = vec![false; n];
+    let mut excluded = vec![false; n];
+    // shortest edge from every node outside to the forest: (length, forest node)
+    let mut closest = vec![(i32::MAX, usize::MAX); n];
+    let mut x = 0;
+    in_forest[0] = true;
+    for _ in 1..n {
+        if start_time.elapsed().as_millis() >= max_processing_ms {
+            return None;
+        }
+        for &y in &without[x] {
+            excluded[y] = true;
+        }
+        let mut next = usize::MAX;
+        for y in 0..n {
+            if in_forest[y] {
+                continue;
+            }
+            if !excluded[y] {
+                let length = graph.get_edge(x as i32, y as i32);
+                if length < closest[y].0 {
+                    closest[y] = (length, x);
+                }
+            }
+            if next == usize::MAX || closest[y].0 < closest[next].0 {
+                next = y;
+            }
+        }
+        for &y in &without[x] {
+            excluded[y] = false;
+        }
+        in_forest[next] = true;
+        // without any edge left to the forest it starts a new tree
+        let (length, parent) = closest[next];
+        if parent != usize::MAX {
+            forest.push((length, parent.min(next), parent.max(next)));
+        }
+        x = next;
+    }
+    Some(forest)
+}
+
+// Whether the graph on the nodes 0 .. n with the given edges is connected
+// and stays connected after removing any single node.
+fn biconnected(n: usize, edges: &[(i32, usize, usize)]) -> bool {
+    two_connected(n, edges, false)
+}
+
+// Whether the graph on the nodes 0 .. n with the given edges, at most one
+// between two nodes, is connected and stays connected after removing any
+// single edge.
+fn bridgeless(n: usize, edges: &[(i32, usize, usize)]) -> bool {
+    two_connected(n, edges, true)
+}
+
+fn two_connected(n: usize, edges: &[(i32, usize, usize)], edges_only: bool) -> bool {
+    let mut adjacent = vec![Vec::new(); n];
+    for &(_, x, y) in edges {
+        adjacent[x].push(y);
+        adjacent[y].push(x);
+    }
+    // depth first search from node 0 with low points, iterative because
+    // paths of n nodes overflow the stack; (node, next neighbor to look at)
+    let mut order = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut parent = vec![usize::MAX; n];
+    let mut stack = vec![(0, 0)];
+    order[0] = 0;
+    let mut visited = 1;
+    let mut root_children = 0;
+    while let Some(&mut (x, ref mut next)) = stack.last_mut() {
+        if *next < adjacent[x].len() {
+            let y = adjacent[x][*next];
+            *next += 1;
+            if order[y] == usize::MAX {
+                order[y] = visited;
+                low[y] = visited;
+                visited += 1;
+                parent[y] = x;
+                if x == 0 {
+                    root_children += 1;
+                }
+                stack.push((y, 0));
+            } else if y != parent[x] {
+                low[x] = low[x].min(order[y]);
+            }
+        } else {
+            stack.pop();
+            let p = parent[x];
+            if p != usize::MAX {
+                low[p] = low[p].min(low[x]);
+                // nothing below x reaches p or above: (p, x) is a bridge;
+                // nothing reaches above p: p separates them
+                if edges_only && low[x] > order[p] || !edges_only && p != 0 && low[x] >= order[p] {
+                    return false;
+                }
+            }
+        }
+    }
+    visited == n && (edges_only || root_children <= 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use crate::utils::{Graph, SimpleRng};
+
+    #[test]
+    fn biconnected_graphs() {
+        let cycle: Vec<(i32, usize, usize)> = (0..5).map(|x| (1, x, (x + 1) % 5)).collect();
+        assert!(biconnected(5, &cycle));
+        assert!(!biconnected(5, &cycle[..4]));
+        // two triangles sharing node 2
+        let bowtie = [(1, 0, 1), (1, 1, 2), (1, 2, 0), (1, 2, 3), (1, 3, 4), (1, 4, 2)];
+        assert!(!biconnected(5, &bowtie));
+        assert!(!biconnected(6, &bowtie));
+        assert!(bridgeless(5, &cycle) && bridgeless(5, &bowtie));
+        assert!(!bridgeless(5, &cycle[..4]) && !bridgeless(6, &bowtie));
+    }
+
+    fn assert_bottleneck_tour(result: &BottleneckTour, graph: &Graph) {
+        let mut sorted = result.tour.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..graph.num_nodes).collect::<Vec<i32>>());
+        assert_eq!(result.bottleneck, bottleneck(graph, &result.tour));
+        assert!(result.lower_bound <= result.bottleneck);
+    }
+
+    #[test]
+    fn optimal_on_grid_and_clusters() {
+        // a 4 x 4 grid has a tour of unit steps; two squares 90 apart need
+        // two edges between them, which only biconnectivity sees
+        let grid: Vec<(f64, f64)> = (0..16).map(|i| ((i % 4 * 10) as f64, (i / 4 * 10) as f64)).collect();
+        let mut squares: Vec<(f64, f64)> = vec![(0.0, 0.0), (0.0, 10.0), (10.0, 0.0), (10.0, 10.0)];
+        squares.extend(squares.clone().iter().map(|&(x, y)| (x + 100.0, y)));
+        for (points, optimum) in [(grid, 10), (squares, 90)] {
+            let graph = Graph::with_metric(&points, Metric::Euc2d);
+            let candidates = CandidateList::new(&graph, BOUND_NEIGHBORS);
+            let mut tour: Vec<i32> = (0..points.len() as i32).collect();
+            SimpleRng::new(2).shuffle(&mut tour);
+            let result = bottleneck_tour(&graph, &candidates, &candidates, tour, Instant::now(), 500);
+            assert_bottleneck_tour(&result, &graph);
+            assert_eq!(result.bottleneck, optimum);
+            assert_eq!(result.lower_bound, optimum);
+        }
+    }
+
+    #[test]
+    fn forests_join_far_clusters() {
+        // two 7 x 7 grids 200 apart, the nearest neighbors of a node are all
+        // in its own grid; a tour crosses twice
+        let points: Vec<(f64, f64)> = (0..98).map(|i| ((i % 7 * 10 + i / 49 * 260) as f64, (i / 7 % 7 * 10) as f64)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let nearest = CandidateList::new(&graph, BOUND_NEIGHBORS);
+        assert!((0..98).all(|x| nearest.get(x).iter().all(|&y| (x < 49) == (y < 49))));
+        let forests = spanning_forests(&graph, Instant::now(), 1000).unwrap();
+        assert_eq!(forests.len(), 2 * 97);
+        assert!(bridgeless(98, &forests));
+        let tour: Vec<i32> = (0..98).collect();
+        let result = bottleneck_tour(&graph, &nearest, &nearest, tour, Instant::now(), 300);
+        assert_bottleneck_tour(&result, &graph);
+        assert_eq!(result.lower_bound, 200);
+    }
+
+    #[test]
+    fn lower_bound_ignores_candidates() {
+        // lists of the farthest nodes must not raise the degree bound
+        let points: Vec<(f64, f64)> = (0..16).map(|i| ((i % 4 * 10) as f64, (i / 4 * 10) as f64)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let farthest = (0..16)
+            .map(|x| {
+                let mut others: Vec<i32> = (0..16).filter(|&y| y != x).collect();
+                others.sort_by_key(|&y| graph.get_edge(x, y));
+                others.split_off(12)
+            })
+            .collect();
+        let tour: Vec<i32> = (0..16).collect();
+        let nearest = CandidateList::new(&graph, BOUND_NEIGHBORS);
+        let result = bottleneck_tour(&graph, &CandidateList::from_neighbors(farthest), &nearest, tour, Instant::now(), 100);
+        assert_bottleneck_tour(&result, &graph);
+        assert_eq!(result.lower_bound, 10);
+    }
+
+    #[test]
+    fn random_points_improve() {
+        let mut rng = SimpleRng::new(7);
+        for n in [5, 101, 200] {
+            let points: Vec<(f64, f64)> = (0..n).map(|_| (rng.next_f64() * 1000.0, rng.next_f64() * 1000.0)).collect();
+            let graph = Graph::with_metric(&points, Metric::Euc2d);
+            let candidates = CandidateList::new(&graph, 8);
+            let nearest = CandidateList::new(&graph, BOUND_NEIGHBORS);
+            let tour: Vec<i32> = (0..n).collect();
+            let start = bottleneck(&graph, &tour);
+            let result = bottleneck_tour(&graph, &candidates, &nearest, tour, Instant::now(), 300);
+            assert_bottleneck_tour(&result, &graph);
+            assert!(result.bottleneck <= start);
+        }
+    }
+}
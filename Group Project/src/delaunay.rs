@@ -0,0 +1,629 @@
+// Delaunay triangulation of the input points and the sparse candidate graph
+// built from its O(n) edges.
+//
+// The triangulation is the sweep-hull algorithm of delaunator: points are
+// added in order of their distance to a seed triangle, the convex hull is
+// kept as a linked list with an angular hash for finding visible edges, and
+// new triangles are legalized by edge flips. For the Euclidean metrics the
+// Delaunay graph contains a minimum spanning tree and most edges of good
+// tours; for the other metrics it is still a useful candidate graph.
+
+use std::cell::OnceCell;
+use crate::kdtree::KdTree;
+use crate::metric::Metric;
+use crate::neighbors::CandidateList;
+use crate::sparse_graph::SparseGraph;
+use crate::utils::{DisjointSet, kruskal};
+
+const NONE: usize = usize::MAX;
+const EPSILON: f64 = f64::EPSILON;
+
+struct Triangulation<'a> {
+    points: &'a [(f64, f64)],
+    // three point ids per triangle, counter-clockwise
+    triangles: Vec<usize>,
+    // opposite half-edge of every half-edge, NONE on the convex hull
+    halfedges: Vec<usize>,
+    hull_prev: Vec<usize>,
+    hull_next: Vec<usize>,
+    hull_tri: Vec<usize>,
+    hull_hash: Vec<usize>,
+    hull_start: usize,
+    center: (f64, f64),
+    // points that were skipped as near duplicates, with a point next to them
+    duplicates: Vec<(usize, usize)>,
+}
+
+// true if r lies to the right of the directed line p -> q (delaunator's orientation test)
+fn orient(p: (f64, f64), q: (f64, f64), r: (f64, f64)) -> bool {
+    (q.1 - p.1) * (r.0 - q.0) - (q.0 - p.0) * (r.1 - q.1) < 0.0
+}
+
+fn in_circle(a: (f64, f64), b: (f64, f64), c: (f64, f64), p: (f64, f64)) -> bool {
+    let (dx, dy) = (a.0 - p.0, a.1 - p.1);
+    let (ex, ey) = (b.0 - p.0, b.1 - p.1);
+    let (fx, fy) = (c.0 - p.0, c.1 - p.1);
+    let ap = dx * dx + dy * dy;
+    let bp = ex * ex + ey * ey;
+    let cp = fx * fx + fy * fy;
+    dx * (ey * cp - bp * fy) - dy * (ex * cp - bp * fx) + ap * (ex * fy - ey * fx) < 0.0
+}
+
+// circumcenter relative to a
+fn circumcenter_offset(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> (f64, f64) {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (ex, ey) = (c.0 - a.0, c.1 - a.1);
+    let bl = dx * dx + dy * dy;
+    let cl = ex * ex + ey * ey;
+    let d = 0.5 / (dx * ey - dy * ex);
+    ((ey * bl - dy * cl) * d, (dx * cl - ex * bl) * d)
+}
+
+fn circumradius(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    let (x, y) = circumcenter_offset(a, b, c);
+    let r = x * x + y * y;
+    if r.is_nan() { f64::INFINITY } else { r }
+}
+
+fn squared_distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0) * (a.0 - b.0) + (a.1 - b.1) * (a.1 - b.1)
+}
+
+// monotone in the angle of (dx, dy), in [0, 1]
+fn pseudo_angle(dx: f64, dy: f64) -> f64 {
+    let p = dx / (dx.abs() + dy.abs());
+    (if dy > 0.0 { 3.0 - p } else { 1.0 + p }) / 4.0
+}
+
+impl<'a> Triangulation<'a> {
+    // None if all points are collinear (or there are fewer than three)
+    fn new(points: &'a [(f64, f64)]) -> Option<Triangulation<'a>> {
+        let n = points.len();
+        if n < 3 {
+            return None;
+        }
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for &(x, y) in points {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        let bbox_center = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+
+        // seed triangle: the point closest to the center, its nearest
+        // neighbor and the point giving the smallest circumcircle
+        let closest = |from: (f64, f64), skip: &[usize]| {
+            (0..n).filter(|i| !skip.contains(i))
+                .map(|i| (squared_distance(from, points[i]), i))
+                .filter(|&(d, _)| skip.is_empty() || d > 0.0)
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, i)| i)
+        };
+        let i0 = closest(bbox_center, &[])?;
+        let mut i1 = closest(points[i0], &[i0])?;
+        let (mut min_radius, mut i2) = (f64::INFINITY, NONE);
+        for i in 0..n {
+            if i == i0 || i == i1 {
+                continue;
+            }
+            let r = circumradius(points[i0], points[i1], points[i]);
+            if r < min_radius {
+                min_radius = r;
+                i2 = i;
+            }
+        }
+        if min_radius == f64::INFINITY {
+            return None;
+        }
+        if orient(points[i0], points[i1], points[i2]) {
+            std::mem::swap(&mut i1, &mut i2);
+        }
+        let offset = circumcenter_offset(points[i0], points[i1], points[i2]);
+        let center = (points[i0].0 + offset.0, points[i0].1 + offset.1);
+
+        let hash_size = (n as f64).sqrt().ceil() as usize;
+        let mut t = Triangulation {
+            points,
+            triangles: Vec::with_capacity(6 * n),
+            halfedges: Vec::with_capacity(6 * n),
+            hull_prev: vec![0; n],
+            hull_next: vec![0; n],
+            hull_tri: vec![0; n],
+            hull_hash: vec![NONE; hash_size],
+            hull_start: i0,
+            center,
+            duplicates: Vec::new(),
+        };
+
+        // sweep the points by distance from the seed circumcenter
+        let dists: Vec<f64> = points.iter().map(|&p| squared_distance(p, center)).collect();
+        let mut ids: Vec<usize> = (0..n).collect();
+        ids.sort_unstable_by(|&a, &b| dists[a].total_cmp(&dists[b]));
+
+        t.hull_next[i0] = i1;
+        t.hull_prev[i2] = i1;
+        t.hull_next[i1] = i2;
+        t.hull_prev[i0] = i2;
+        t.hull_next[i2] = i0;
+        t.hull_prev[i1] = i0;
+        t.hull_tri[i0] = 0;
+        t.hull_tri[i1] = 1;
+        t.hull_tri[i2] = 2;
+        for i in [i0, i1, i2] {
+            let key = t.hash_key(points[i]);
+            t.hull_hash[key] = i;
+        }
+        t.add_triangle(i0, i1, i2, NONE, NONE, NONE);
+
+        let mut previous = i0;
+        for &i in &ids {
+            let p = points[i];
+            if i == i0 || i == i1 || i == i2 {
+                previous = i;
+                continue;
+            }
+            if (p.0 - points[previous].0).abs() <= EPSILON && (p.1 - points[previous].1).abs() <= EPSILON {
+                t.duplicates.push((i, previous));
+                continue;
+            }
+            if !t.insert(i) {
+                // numerically on the hull of a near duplicate
+                t.duplicates.push((i, previous));
+                continue;
+            }
+            previous = i;
+        }
+        Some(t)
+    }
+
+    fn hash_key(&self, p: (f64, f64)) -> usize {
+        let size = self.hull_hash.len();
+        ((pseudo_angle(p.0 - self.center.0, p.1 - self.center.1) * size as f64).floor() as usize) % size
+    }
+
+    fn insert(&mut self, i: usize) -> bool {
+        let points = self.points;
+        let p = points[i];
+
+        // find a visible edge on the convex hull using the hash
+        let mut start = 0;
+        let key = self.hash_key(p);
+        for j in 0..self.hull_hash.len() {
+            start = self.hull_hash[(key + j) % self.hull_hash.len()];
+            if start != NONE && start != self.hull_next[start] {
+                break;
+            }
+        }
+        start = self.hull_prev[start];
+        let mut e = start;
+        loop {
+            let q = self.hull_next[e];
+            if orient(p, points[e], points[q]) {
+                break;
+            }
+            e = q;
+            if e == start {
+                return false;
+            }
+        }
+
+        // first triangle from the point
+        let t = self.add_triangle(e, i, self.hull_next[e], NONE, NONE, self.hull_tri[e]);
+        self.hull_tri[i] = self.legalize(t + 2);
+        self.hull_tri[e] = t;
+
+        // walk forward through the hull, adding triangles
+        let mut n = self.hull_next[e];
+        loop {
+            let q = self.hull_next[n];
+            if !orient(p, points[n], points[q]) {
+                break;
+            }
+            let t = self.add_triangle(n, i, q, self.hull_tri[i], NONE, self.hull_tri[n]);
+            self.hull_tri[i] = self.legalize(t + 2);
+            // mark as removed
+            self.hull_next[n] = n;
+            n = q;
+        }
+
+        // walk backward from the other side
+        if e == start {
+            loop {
+                let q = self.hull_prev[e];
+                if !orient(p, points[q], points[e]) {
+                    break;
+                }
+                let t = self.add_triangle(q, i, e, NONE, self.hull_tri[e], self.hull_tri[q]);
+                self.legalize(t + 2);
+                self.hull_tri[q] = t;
+                self.hull_next[e] = e;
+                e = q;
+            }
+        }
+
+        self.hull_start = e;
+        self.hull_prev[i] = e;
+        self.hull_next[e] = i;
+        self.hull_prev[n] = i;
+        self.hull_next[i] = n;
+
+        let key = self.hash_key(p);
+        self.hull_hash[key] = i;
+        let key = self.hash_key(points[e]);
+        self.hull_hash[key] = e;
+        true
+    }
+
+    fn link(&mut self, a: usize, b: usize) {
+        self.halfedges[a] = b;
+        if b != NONE {
+            self.halfedges[b] = a;
+        }
+    }
+
+    fn add_triangle(&mut self, i0: usize, i1: usize, i2: usize, a: usize, b: usize, c: usize) -> usize {
+        let t = self.triangles.len();
+        self.triangles.extend([i0, i1, i2]);
+        self.halfedges.extend([NONE, NONE, NONE]);
+        self.link(t, a);
+        self.link(t + 1, b);
+        self.link(t + 2, c);
+        t
+    }
+
+    // flip edges until the triangles around half-edge a satisfy the Delaunay condition
+    fn legalize(&mut self, mut a: usize) -> usize {
+        let mut stack = Vec::new();
+        let mut ar;
+        loop {
+            let b = self.halfedges[a];
+            let a0 = a - a % 3;
+            ar = a0 + (a + 2) % 3;
+
+            if b == NONE {
+                match stack.pop() {
+                    Some(next) => { a = next; continue; }
+                    None => break,
+                }
+            }
+
+            let b0 = b - b % 3;
+            let al = a0 + (a + 1) % 3;
+            let bl = b0 + (b + 2) % 3;
+            let p0 = self.triangles[ar];
+            let pr = self.triangles[a];
+            let pl = self.triangles[al];
+            let p1 = self.triangles[bl];
+
+            if in_circle(self.points[p0], self.points[pr], self.points[pl], self.points[p1]) {
+                self.triangles[a] = p1;
+                self.triangles[b] = p0;
+                let hbl = self.halfedges[bl];
+                // edge swapped on the other side of the hull; fix the reference
+                if hbl == NONE {
+                    let mut e = self.hull_start;
+                    loop {
+                        if self.hull_tri[e] == bl {
+                            self.hull_tri[e] = a;
+                            break;
+                        }
+                        e = self.hull_prev[e];
+                        if e == self.hull_start {
+                            break;
+                        }
+                    }
+                }
+                self.link(a, hbl);
+                let har = self.halfedges[ar];
+                self.link(b, har);
+                self.link(ar, bl);
+                stack.push(b0 + (b + 1) % 3);
+            } else {
+                match stack.pop() {
+                    Some(next) => a = next,
+                    None => break,
+                }
+            }
+        }
+        ar
+    }
+}
+
+// The undirected edges of the Delaunay triangulation. Duplicate points get an
+// edge to the point they coincide with; collinear inputs give the path along
+// the line. The graph is connected.
+pub fn delaunay_edges(points: &[(f64, f64)]) -> Vec<(i32, i32)> {
+    // triangulate every location once
+    let mut ids: Vec<usize> = (0..points.len()).collect();
+    ids.sort_by(|&a, &b| points[a].0.total_cmp(&points[b].0).then(points[a].1.total_cmp(&points[b].1)));
+    let mut unique: Vec<usize> = Vec::with_capacity(points.len());
+    let mut edges = Vec::new();
+    for &i in &ids {
+        match unique.last() {
+            Some(&j) if points[j] == points[i] => edges.push((i as i32, j as i32)),
+            _ => unique.push(i),
+        }
+    }
+    let unique_points: Vec<(f64, f64)> = unique.iter().map(|&i| points[i]).collect();
+
+    match Triangulation::new(&unique_points) {
+        Some(t) => {
+            for e in 0..t.triangles.len() {
+                let opposite = t.halfedges[e];
+                if opposite == NONE || e > opposite {
+                    let next = if e % 3 == 2 { e - 2 } else { e + 1 };
+                    edges.push((unique[t.triangles[e]] as i32, unique[t.triangles[next]] as i32));
+                }
+            }
+            edges.extend(t.duplicates.iter().map(|&(i, j)| (unique[i] as i32, unique[j] as i32)));
+        }
+        None => {
+            // all points on a line, which is sorted along x (or y if vertical)
+            let vertical = unique.iter().all(|&i| points[i].0 == points[unique[0]].0);
+            if vertical {
+                unique.sort_by(|&a, &b| points[a].1.total_cmp(&points[b].1));
+            }
+            edges.extend(unique.windows(2).map(|w| (w[0] as i32, w[1] as i32)));
+        }
+    }
+    edges
+}
+
+// Sparse candidate graph: the Delaunay edges with their lengths under the
+// given metric, sorted by length. Distances are computed from the points,
+// the n x n matrix of Graph is never built.
+pub struct DelaunayGraph<'a> {
+    points: &'a [(f64, f64)],
+    metric: Metric,
+    // (length, x, y) with x < y, shortest first
+    edges: Vec<(i32, i32, i32)>,
+    adjacency_list: Vec<Vec<i32>>,
+}
+
+impl<'a> DelaunayGraph<'a> {
+    pub fn new(points: &'a [(f64, f64)], metric: Metric) -> DelaunayGraph<'a> {
+        let mut edges: Vec<(i32, i32, i32)> = delaunay_edges(points).into_iter()
+            .map(|(x, y)| (metric.distance(points[x as usize], points[y as usize]), x.min(y), x.max(y)))
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        let mut adjacency_list = vec![Vec::new(); points.len()];
+        for &(_, x, y) in &edges {
+            adjacency_list[x as usize].push(y);
+            adjacency_list[y as usize].push(x);
+        }
+        DelaunayGraph { points, metric, edges, adjacency_list }
+    }
+
+    pub fn get_neighbors(&self, x: i32) -> &[i32] {
+        &self.adjacency_list[x as usize]
+    }
+
+    fn distance(&self, x: i32, y: i32) -> i32 {
+        self.metric.distance(self.points[x as usize], self.points[y as usize])
+    }
+
+    // Kruskal on the Delaunay edges; a minimum spanning tree of the whole
+    // graph for the Euclidean metrics
+    pub fn get_min_spanning_tree(&self) -> SparseGraph {
+        kruskal(self.points.len(), &mut self.edges.clone())
+    }
+
+    // Greedy edge construction on the Delaunay edges. The paths that are
+    // left over are joined end to end, each time to the closest free end of
+    // another path.
+    pub fn greedy_tour(&self) -> Vec<i32> {
+        let n = self.points.len();
+        if n < 3 {
+            return (0..n as i32).collect();
+        }
+        let mut paths = SparseGraph::new(n as i32);
+        let mut fragments = DisjointSet::new(n);
+        for &(_, x, y) in &self.edges {
+            if paths.get_vertex_degree(x) < 2 && paths.get_vertex_degree(y) < 2 && fragments.union(x, y) {
+                paths.add_edge(x, y);
+            }
+        }
+
+        // every fragment as (one end, other end); single nodes have both ends equal
+        let mut visited = vec![false; n];
+        let mut fragment_list = Vec::new();
+        for x in 0..n as i32 {
+            if !visited[x as usize] && paths.get_vertex_degree(x) < 2 {
+                let (mut prev, mut cur) = (x, x);
+                visited[x as usize] = true;
+                while let Some(&next) = paths.adjacency_list[cur as usize].iter().find(|&&y| y != prev) {
+                    visited[next as usize] = true;
+                    (prev, cur) = (cur, next);
+                }
+                fragment_list.push((x, cur));
+            }
+        }
+
+        // chain the fragments, nearest free end first
+        let mut used = vec![false; fragment_list.len()];
+        used[0] = true;
+        let mut end = fragment_list[0].1;
+        for _ in 1..fragment_list.len() {
+            let mut best = (i32::MAX, 0, false);
+            for (f, &(a, b)) in fragment_list.iter().enumerate() {
+                if used[f] {
+                    continue;
+                }
+                for (node, reversed) in [(a, false), (b, true)] {
+                    let d = self.distance(end, node);
+                    if d < best.0 {
+                        best = (d, f, reversed);
+                    }
+                }
+            }
+            let (_, f, reversed) = best;
+            used[f] = true;
+            let (a, b) = fragment_list[f];
+            let (near, far) = if reversed { (b, a) } else { (a, b) };
+            paths.add_edge(end, near);
+            end = far;
+        }
+        if fragment_list.len() > 1 || fragment_list[0].0 != fragment_list[0].1 {
+            paths.add_edge(end, fragment_list[0].0);
+        }
+
+        // walk the cycle
+        let mut tour = Vec::with_capacity(n);
+        let (mut prev, mut cur) = (-1, 0);
+        for _ in 0..n {
+            tour.push(cur);
+            let neighbors = &paths.adjacency_list[cur as usize];
+            let next = if neighbors[0] != prev { neighbors[0] } else { neighbors[1] };
+            (prev, cur) = (cur, next);
+        }
+        tour
+    }
+
+    // The k nearest nodes plus all Delaunay neighbors, closest first. The
+    // Delaunay edges connect clusters that the nearest neighbors alone
+    // would leave without any candidate edge between them.
+    pub fn candidate_list(&self, k: usize) -> CandidateList {
+        let n = self.points.len();
+        let tree = KdTree::new(self.points);
+        let mut neighbors = Vec::with_capacity(n);
+        for x in 0..n as i32 {
+            let mut near: Vec<i32> = tree.k_nearest(self.points[x as usize], k + 1).into_iter()
+                .map(|y| y as i32)
+                .filter(|&y| y != x)
+                .take(k)
+                .collect();
+            for &y in self.get_neighbors(x) {
+                if !near.contains(&y) {
+                    near.push(y);
+                }
+            }
+            near.sort_by_cached_key(|&y| self.distance(x, y));
+            neighbors.push(near);
+        }
+        CandidateList::from_neighbors(neighbors)
+    }
+}
+
+// The Delaunay graph, triangulated when first asked for: the exact solver
+// and the matrix solvers under non-Euclidean metrics never need it.
+pub struct LazyDelaunay<'a> {
+    points: &'a [(f64, f64)],
+    metric: Metric,
+    graph: OnceCell<DelaunayGraph<'a>>,
+}
+
+impl<'a> LazyDelaunay<'a> {
+    pub fn new(points: &'a [(f64, f64)], metric: Metric) -> LazyDelaunay<'a> {
+        LazyDelaunay { points, metric, graph: OnceCell::new() }
+    }
+
+    pub fn is_euclidean(&self) -> bool {
+        self.metric.is_euclidean()
+    }
+
+    pub fn get(&self) -> &DelaunayGraph<'a> {
+        self.graph.get_or_init(|| DelaunayGraph::new(self.points, self.metric))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::DistanceOracle;
+    use crate::utils::{Graph, SimpleRng};
+
+    fn random_points(n: usize, seed: u64) -> Vec<(f64, f64)> {
+        let mut rng = SimpleRng::new(seed);
+        (0..n).map(|_| ((rng.next_f64() * 1000.0).round(), (rng.next_f64() * 1000.0).round())).collect()
+    }
+
+    fn tree_weight(tree: &SparseGraph, graph: &Graph, n: i32) -> i32 {
+        let mut weight = 0;
+        for x in 0..n {
+            for &y in &tree.adjacency_list[x as usize] {
+                if x < y {
+                    weight += graph.get_edge(x, y);
+                }
+            }
+        }
+        weight
+    }
+
+    #[test]
+    fn square_with_center() {
+        let points = vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (1.0, 1.0)];
+        let edges = delaunay_edges(&points);
+        // four hull edges and four spokes
+        assert_eq!(edges.len(), 8);
+        assert!(edges.iter().all(|&(x, y)| x != y));
+    }
+
+    #[test]
+    fn cocircular_square() {
+        let points = vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)];
+        let edges = delaunay_edges(&points);
+        assert_eq!(edges.len(), 5);
+        assert!(edges.iter().all(|&(x, y)| x != y));
+    }
+
+    #[test]
+    fn empty_circumcircles() {
+        let points = random_points(200, 4);
+        let t = Triangulation::new(&points).unwrap();
+        for tri in t.triangles.chunks(3) {
+            let (a, b, c) = (points[tri[0]], points[tri[1]], points[tri[2]]);
+            let offset = circumcenter_offset(a, b, c);
+            let center = (a.0 + offset.0, a.1 + offset.1);
+            let r = squared_distance(a, center);
+            for (i, &p) in points.iter().enumerate() {
+                if !tri.contains(&i) {
+                    assert!(squared_distance(p, center) >= r * (1.0 - 1e-9));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mst_matches_dense() {
+        let points = random_points(300, 2);
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let delaunay = DelaunayGraph::new(&points, Metric::Euc2d);
+        assert!(delaunay.edges.len() < 3 * 300);
+        let sparse = tree_weight(&delaunay.get_min_spanning_tree(), &graph, 300);
+        let dense = tree_weight(&graph.get_min_spanning_tree(), &graph, 300);
+        assert_eq!(sparse, dense);
+    }
+
+    #[test]
+    fn candidates_connect_clusters() {
+        // two far apart clusters of five points
+        let points: Vec<(f64, f64)> = (0..10).map(|i| ((i % 5) as f64 + (i / 5) as f64 * 1000.0, (i % 2) as f64)).collect();
+        let candidates = DelaunayGraph::new(&points, Metric::Euc2d).candidate_list(3);
+        assert!((0..10).any(|x| candidates.get(x).iter().any(|&y| (x < 5) != (y < 5))));
+        assert!((0..10).all(|x| candidates.get(x).len() >= 3 && !candidates.get(x).contains(&x)));
+    }
+
+    #[test]
+    fn lazy_builds_once_on_demand() {
+        let points = random_points(50, 3);
+        let lazy = LazyDelaunay::new(&points, Metric::Euc2d);
+        assert!(lazy.is_euclidean() && lazy.graph.get().is_none());
+        let edges = lazy.get().edges.len();
+        assert!(std::ptr::eq(lazy.get(), lazy.get()));
+        assert_eq!(edges, DelaunayGraph::new(&points, Metric::Euc2d).edges.len());
+    }
+
+    #[test]
+    fn greedy_tour_with_duplicates_and_lines() {
+        let mut points = random_points(100, 8);
+        points.extend_from_within(..10);
+        for points in [points, (0..20).map(|i| (i as f64, 2.0 * i as f64)).collect()] {
+            let tour = DelaunayGraph::new(&points, Metric::Euc2d).greedy_tour();
+            let mut sorted = tour.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..points.len() as i32).collect::<Vec<i32>>());
+        }
+    }
+}
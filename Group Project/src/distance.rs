@@ -0,0 +1,198 @@
+// Edge lengths as the solvers see them.
+//
+// The local search only ever asks for the length of single edges, so it does
+// not care whether they come from a precomputed matrix or straight from the
+// coordinates. Which oracle to use is a trade between memory and speed:
+//
+//   FlatMatrix     n^2 lengths in one allocation, the fastest lookups
+//   Graph          the triangular matrix, half the memory of FlatMatrix
+//   LazyDistances  O(1) memory, computed from the points on every miss of a
+//                  small cache; the only choice once n^2 does not fit
+//
+// Only depends on metric.rs and sparse_graph.rs so that the single-file
+// solutions can include it.
+
+use std::cell::RefCell;
+use crate::metric::Metric;
+use crate::sparse_graph::SparseGraph;
+
+pub trait DistanceOracle {
+    fn num_nodes(&self) -> usize;
+
+    // length of the edge between two different nodes
+    fn get_edge(&self, x: i32, y: i32) -> i32;
+
+    // Prim's algorithm, O(n^2) time but only O(n) memory on top of the oracle
+    fn get_min_spanning_tree(&self) -> SparseGraph {
+        let n = self.num_nodes();
+        let mut sparse_graph = SparseGraph::new(n as i32);
+        if n == 0 {
+            return sparse_graph;
+        }
+        let mut in_tree = vec![false; n];
+        // shortest edge from every node outside to the tree: (length, tree node)
+        let mut closest = vec![(i32::MAX, 0); n];
+        let mut x = 0;
+        in_tree[0] = true;
+        for _ in 1..n {
+            let mut next = usize::MAX;
+            for y in 0..n {
+                if in_tree[y] {
+                    continue;
+                }
+                let length = self.get_edge(x as i32, y as i32);
+                if length < closest[y].0 {
+                    closest[y] = (length, x);
+                }
+                if next == usize::MAX || closest[y].0 < closest[next].0 {
+                    next = y;
+                }
+            }
+            in_tree[next] = true;
+            sparse_graph.add_edge(closest[next].1 as i32, next as i32);
+            x = next;
+        }
+        sparse_graph
+    }
+}
+
+// Full n x n matrix stored row by row, so the lengths from a node to all of
+// its candidates usually share a few cache lines.
+pub struct FlatMatrix {
+    num_nodes: usize,
+    lengths: Vec<i32>,
+}
+
+impl FlatMatrix {
+    pub fn new(points: &[(f64, f64)], metric: Metric) -> FlatMatrix {
+        let n = points.len();
+        let mut lengths = vec![0; n * n];
+        for i in 0..n {
+            for j in i + 1..n {
+                let length = metric.distance(points[i], points[j]);
+                lengths[i * n + j] = length;
+                lengths[j * n + i] = length;
+            }
+        }
+        FlatMatrix { num_nodes: n, lengths }
+    }
+}
+
+impl DistanceOracle for FlatMatrix {
+    fn num_nodes(&self) -> usize {
+        self.num_nodes
+    }
+
+    fn get_edge(&self, x: i32, y: i32) -> i32 {
+        self.lengths[x as usize * self.num_nodes + y as usize]
+    }
+}
+
+// the cache holds CACHE_SETS * CACHE_WAYS pairs, about 1.5 MB
+const CACHE_SETS: usize = 1 << 14;
+const CACHE_WAYS: usize = 4;
+const EMPTY: u64 = u64::MAX;
+
+#[derive(Clone, Copy)]
+struct CacheEntry {
+    // smaller node in the high, larger in the low 32 bits
+    key: u64,
+    length: i32,
+    last_used: u64,
+}
+
+// Set associative: a pair can only be stored in the CACHE_WAYS entries of the
+// set its key hashes to, and a miss evicts the least recently used of them.
+struct Cache {
+    entries: Vec<CacheEntry>,
+    clock: u64,
+}
+
+// Lengths computed from the coordinates on demand. The local search asks for
+// the same few edges between a node and its candidates over and over, which
+// the cache keeps; this pays off for the expensive metrics like GEO.
+pub struct LazyDistances<'a> {
+    points: &'a [(f64, f64)],
+    metric: Metric,
+    // get_edge takes &self, like the lookup in a matrix
+    cache: RefCell<Cache>,
+}
+
+impl<'a> LazyDistances<'a> {
+    pub fn new(points: &'a [(f64, f64)], metric: Metric) -> LazyDistances<'a> {
+        let empty = CacheEntry { key: EMPTY, length: 0, last_used: 0 };
+        LazyDistances {
+            points,
+            metric,
+            cache: RefCell::new(Cache { entries: vec![empty; CACHE_SETS * CACHE_WAYS], clock: 0 }),
+        }
+    }
+}
+
+impl DistanceOracle for LazyDistances<'_> {
+    fn num_nodes(&self) -> usize {
+        self.points.len()
+    }
+
+    fn get_edge(&self, x: i32, y: i32) -> i32 {
+        let key = ((x.min(y) as u64) << 32) | x.max(y) as u64;
+        // Fibonacci hashing, the top bits are the best mixed
+        let set = (key.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (64 - CACHE_SETS.trailing_zeros())) as usize;
+        let mut cache = self.cache.borrow_mut();
+        cache.clock += 1;
+        let clock = cache.clock;
+        let ways = &mut cache.entries[set * CACHE_WAYS..(set + 1) * CACHE_WAYS];
+
+        if let Some(entry) = ways.iter_mut().find(|entry| entry.key == key) {
+            entry.last_used = clock;
+            return entry.length;
+        }
+        let length = self.metric.distance(self.points[x as usize], self.points[y as usize]);
+        // empty entries were never used, so they go first
+        let victim = ways.iter_mut().min_by_key(|entry| entry.last_used).unwrap();
+        *victim = CacheEntry { key, length, last_used: clock };
+        length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{Graph, SimpleRng};
+
+    #[test]
+    fn oracles_agree() {
+        let mut rng = SimpleRng::new(4);
+        let points: Vec<(f64, f64)> = (0..400).map(|_| (rng.next_f64() * 90.0 - 45.0, rng.next_f64() * 180.0 - 90.0)).collect();
+        for metric in [Metric::Euc2d, Metric::Geo] {
+            let graph = Graph::with_metric(&points, metric);
+            let flat = FlatMatrix::new(&points, metric);
+            let lazy = LazyDistances::new(&points, metric);
+            assert_eq!(flat.num_nodes(), 400);
+            assert_eq!(lazy.num_nodes(), 400);
+            // 80k pairs, more than the cache holds, so entries get evicted
+            // and looked up again
+            for _ in 0..2 {
+                for x in 0..400 {
+                    for y in 0..400 {
+                        if x != y {
+                            assert_eq!(flat.get_edge(x, y), graph.get_edge(x, y));
+                            assert_eq!(lazy.get_edge(x, y), graph.get_edge(x, y));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cache_keeps_recent_pairs() {
+        let points: Vec<(f64, f64)> = (0..3).map(|i| (i as f64, 0.0)).collect();
+        let lazy = LazyDistances::new(&points, Metric::Euc2d);
+        assert_eq!(lazy.get_edge(0, 2), 2);
+        assert_eq!(lazy.get_edge(2, 0), 2);
+        let cache = lazy.cache.borrow();
+        assert_eq!(cache.entries.iter().filter(|entry| entry.key != EMPTY).count(), 1);
+        assert_eq!(cache.clock, 2);
+    }
+}
@@ -0,0 +1,427 @@
+// Euler circuits for the Christofides heuristic.
+//
+// The spanning tree plus the matching is a connected multigraph in which
+// every node has even degree, so it has a closed walk using every edge exactly
+// once. Hierholzer's algorithm finds it in O(n + m): walk unused edges until
+// stuck (which can only happen back at the start of the sub-walk), then back
+// up and splice in further sub-walks from the nodes passed on the way.
+
+use std::collections::HashMap;
+use crate::distance::DistanceOracle;
+use crate::sparse_graph::SparseGraph;
+use crate::utils::{DisjointSet, SimpleRng, tour_length};
+
+// most states best_occurrence follows at every visit
+const MAX_STATES: usize = 1 << 10;
+
+// How a circuit is turned into a tour. Without the triangle inequality none
+// of them is guaranteed to beat FirstVisit, so the others fall back to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shortcut {
+    // keep the first visit of every node
+    FirstVisit,
+    // for every repeated node, keep the visit that leaves the shortest tour
+    BestOccurrence,
+    // BestOccurrence on the circuit from node 0 and on tries - 1 more
+    // circuits from random start nodes with shuffled edge orders
+    MultiStart { tries: usize, seed: u64 },
+}
+
+pub struct ShortcutTour {
+    pub tour: Vec<i32>,
+    pub length: i64,
+    // how much shorter the tour is than FirstVisit on the circuit from node 0
+    pub gain: i64,
+}
+
+// every edge appears in both adjacency lists; number them so that the two
+// copies of parallel edges can be told apart: (neighbor, edge id)
+fn incident_edges(graph: &SparseGraph) -> (Vec<Vec<(i32, usize)>>, usize) {
+    let n = graph.adjacency_list.len();
+    let mut incident: Vec<Vec<(i32, usize)>> = vec![Vec::new(); n];
+    let mut num_edges = 0;
+    for x in 0..n {
+        for &y in &graph.adjacency_list[x] {
+            if (x as i32) < y {
+                incident[x].push((y, num_edges));
+                incident[y as usize].push((x as i32, num_edges));
+                num_edges += 1;
+            }
+        }
+    }
+    (incident, num_edges)
+}
+
+// The Euler circuit from start, as the sequence of visited nodes; the first
+// and last node are both start, consecutive nodes are the edges in walk
+// order. Every edge of a parallel pair is used once. The graph must be
+// connected with all degrees even, otherwise not all edges are walked.
+pub fn euler_circuit(graph: &SparseGraph, start: i32) -> Vec<i32> {
+    let (incident, num_edges) = incident_edges(graph);
+    walk(&incident, num_edges, start)
+}
+
+fn walk(incident: &[Vec<(i32, usize)>], num_edges: usize, start: i32) -> Vec<i32> {
+    let mut used = vec![false; num_edges];
+    // incident[x][..next[x]] are all used
+    let mut next = vec![0; incident.len()];
+    let mut circuit = Vec::with_capacity(num_edges + 1);
+    let mut stack = vec![start];
+    while let Some(&x) = stack.last() {
+        let edges = &incident[x as usize];
+        let i = &mut next[x as usize];
+        while *i < edges.len() && used[edges[*i].1] {
+            *i += 1;
+        }
+        if *i < edges.len() {
+            let (y, id) = edges[*i];
+            used[id] = true;
+            stack.push(y);
+        } else {
+            // no edges left here, x is final in the circuit
+            circuit.push(x);
+            stack.pop();
+        }
+    }
+    // the nodes come out in reverse, which is a circuit as well
+    circuit
+}
+
+// Turns a circuit into a tour by keeping only the first visit of every node;
+// with the triangle inequality this does not make it longer.
+pub fn shortcut(circuit: &[i32], num_nodes: usize) -> Vec<i32> {
+    let mut visited = vec![false; num_nodes];
+    let mut tour = Vec::with_capacity(num_nodes);
+    for &x in circuit {
+        if !visited[x as usize] {
+            visited[x as usize] = true;
+            tour.push(x);
+        }
+    }
+    tour
+}
+
+// Euler circuit of the multigraph shortcut to a tour with the given policy.
+pub fn shortcut_tour<D: DistanceOracle>(graph: &D, multigraph: &SparseGraph, policy: Shortcut) -> ShortcutTour {
+    let n = graph.num_nodes();
+    let circuit = euler_circuit(multigraph, 0);
+    let tour = shortcut(&circuit, n);
+    let first_visit = tour_length(graph, &tour);
+    let mut best = ShortcutTour { tour, length: first_visit, gain: 0 };
+
+    let mut consider = |tour: Vec<i32>| {
+        let length = tour_length(graph, &tour);
+        if length < best.length {
+            best = ShortcutTour { tour, length, gain: first_visit - length };
+        }
+    };
+    match policy {
+        Shortcut::FirstVisit => {}
+        Shortcut::BestOccurrence => consider(best_occurrence(graph, &circuit)),
+        Shortcut::MultiStart { tries, seed } => {
+            consider(best_occurrence(graph, &circuit));
+            let (mut incident, num_edges) = incident_edges(multigraph);
+            let mut rng = SimpleRng::new(seed);
+            for _ in 1..tries {
+                for edges in incident.iter_mut() {
+                    rng.shuffle(edges);
+                }
+                let start = rng.gen_range(0, n) as i32;
+                consider(best_occurrence(graph, &walk(&incident, num_edges, start)));
+            }
+        }
+    }
+    best
+}
+
+// A state of the dynamic program in best_occurrence: which nodes of the
+// group are kept already, and the last node kept in the current stretch
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Kept {
+    nodes: Vec<u64>,
+    last: i32,
+}
+
+// a state with its length so far, the index of the state at the step before
+// and the visit if it was kept
+type Step = (Kept, i64, usize, Option<usize>);
+
+// the MAX_STATES shortest states, shortest first; ties are broken by the
+// state so that the tours do not depend on the order of the hash map
+fn shortest_states(states: HashMap<Kept, (i64, usize, Option<usize>)>) -> Vec<Step> {
+    let mut states: Vec<Step> = states.into_iter()
+        .map(|(state, (length, from, kept))| (state, length, from, kept))
+        .collect();
+    states.sort_unstable_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    states.truncate(MAX_STATES);
+    states
+}
+
+// For every repeated node the visit to keep that gives the shortest tour.
+// Nodes visited once are always kept (and so is the first visit if there are
+// none), and the length between two of them only depends on which visits in
+// the stretch between them are kept. Nodes sharing a stretch are decided
+// together, in groups that do not affect each other. A dynamic program walks
+// through the visits of a group in circuit order, keeping or dropping each;
+// its state is which nodes of the group are kept already and the last visit
+// kept in the current stretch. The last visit of a node not yet kept has to
+// be kept. This is exact unless a group has more than MAX_STATES states at
+// some visit, then only the shortest are followed.
+fn best_occurrence<D: DistanceOracle>(graph: &D, circuit: &[i32]) -> Vec<i32> {
+    if circuit.len() < 3 {
+        return shortcut(circuit, graph.num_nodes());
+    }
+    // without the closing repetition of the start
+    let m = circuit.len() - 1;
+    let nodes = &circuit[..m];
+    let mut count = vec![0; graph.num_nodes()];
+    for &x in nodes {
+        count[x as usize] += 1;
+    }
+    let anchor = (0..m).find(|&i| count[nodes[i] as usize] == 1).unwrap_or(0);
+    let fixed = |i: usize| i == anchor || count[nodes[i] as usize] == 1;
+    // with repeated nodes the ends of a stretch can be the same node
+    let distance = |x: i32, y: i32| if x == y { 0 } else { graph.get_edge(x, y) as i64 };
+
+    // the stretches between fixed visits from the anchor on, without the
+    // other visits of the anchor's node: (fixed before, visits, fixed after)
+    let mut stretches: Vec<(i32, Vec<usize>, i32)> = Vec::new();
+    let mut visits = Vec::new();
+    let mut before = nodes[anchor];
+    for k in 1..=m {
+        let i = (anchor + k) % m;
+        if fixed(i) {
+            if !visits.is_empty() {
+                stretches.push((before, std::mem::take(&mut visits), nodes[i]));
+            }
+            before = nodes[i];
+        } else if nodes[i] != nodes[anchor] {
+            visits.push(i);
+        }
+    }
+    // nodes in a stretch together are in one group
+    let mut groups = DisjointSet::new(graph.num_nodes());
+    for (_, visits, _) in &stretches {
+        for pair in visits.windows(2) {
+            groups.union(nodes[pair[0]], nodes[pair[1]]);
+        }
+    }
+    let mut group_stretches: HashMap<i32, Vec<usize>> = HashMap::new();
+    for (s, (_, visits, _)) in stretches.iter().enumerate() {
+        group_stretches.entry(groups.find(nodes[visits[0]])).or_default().push(s);
+    }
+
+    let mut keep: Vec<bool> = (0..m).map(fixed).collect();
+    for group in group_stretches.values() {
+        // the nodes of the group numbered from 0, and the last visit of each
+        let mut index = HashMap::new();
+        let mut last_visit = Vec::new();
+        for &s in group {
+            for &i in &stretches[s].1 {
+                let next = index.len();
+                let j = *index.entry(nodes[i]).or_insert(next);
+                if j == last_visit.len() {
+                    last_visit.push(i);
+                }
+                last_visit[j] = i;
+            }
+        }
+        let words = index.len().div_ceil(64);
+
+        // the states after every visit and stretch end
+        let mut steps: Vec<Vec<Step>> = vec![vec![(Kept { nodes: vec![0; words], last: -1 }, 0, 0, None)]];
+        for &s in group {
+            let (before, ref visits, after) = stretches[s];
+            for &i in visits {
+                let j = index[&nodes[i]];
+                let mut next: HashMap<Kept, (i64, usize, Option<usize>)> = HashMap::new();
+                let mut add = |state: Kept, length: i64, from: usize, kept: Option<usize>| {
+                    let entry = next.entry(state).or_insert((i64::MAX, 0, None));
+                    if length < entry.0 {
+                        *entry = (length, from, kept);
+                    }
+                };
+                for (from, (state, length, _, _)) in steps.last().unwrap().iter().enumerate() {
+                    let last = if state.last < 0 { before } else { state.last };
+                    let done = state.nodes[j / 64] >> (j % 64) & 1 == 1;
+                    if !done {
+                        let mut kept = state.clone();
+                        kept.nodes[j / 64] |= 1 << (j % 64);
+                        kept.last = nodes[i];
+                        add(kept, length + distance(last, nodes[i]), from, Some(i));
+                    }
+                    if done || i != last_visit[j] {
+                        add(state.clone(), *length, from, None);
+                    }
+                }
+                steps.push(shortest_states(next));
+            }
+            // close the stretch; states that now only differ in the last
+            // visit are merged
+            let mut next: HashMap<Kept, (i64, usize, Option<usize>)> = HashMap::new();
+            for (from, (state, length, _, _)) in steps.last().unwrap().iter().enumerate() {
+                let last = if state.last < 0 { before } else { state.last };
+                let length = length + distance(last, after);
+                let closed = Kept { nodes: state.nodes.clone(), last: -1 };
+                let entry = next.entry(closed).or_insert((i64::MAX, 0, None));
+                if length < entry.0 {
+                    *entry = (length, from, None);
+                }
+            }
+            steps.push(shortest_states(next));
+        }
+
+        // every node is kept by now, the shortest state comes first
+        let mut at = 0;
+        for step in steps.iter().skip(1).rev() {
+            let (_, _, from, kept) = step[at];
+            if let Some(i) = kept {
+                keep[i] = true;
+            }
+            at = from;
+        }
+    }
+    (0..m).map(|k| (anchor + k) % m).filter(|&i| keep[i]).map(|i| nodes[i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use crate::utils::Graph;
+
+    // sorted (min, max) pairs
+    fn edge_multiset(edges: impl Iterator<Item = (i32, i32)>) -> Vec<(i32, i32)> {
+        let mut edges: Vec<(i32, i32)> = edges.map(|(x, y)| (x.min(y), x.max(y))).collect();
+        edges.sort();
+        edges
+    }
+
+    fn assert_euler_circuit(graph: &SparseGraph, edges: &[(i32, i32)], start: i32) {
+        let circuit = euler_circuit(graph, start);
+        assert_eq!(circuit.first(), Some(&start));
+        assert_eq!(circuit.last(), Some(&start));
+        assert_eq!(circuit.len(), edges.len() + 1);
+        let walked = edge_multiset(circuit.windows(2).map(|w| (w[0], w[1])));
+        assert_eq!(walked, edge_multiset(edges.iter().copied()));
+    }
+
+    #[test]
+    fn doubled_random_trees() {
+        let mut rng = SimpleRng::new(8);
+        for n in [1, 2, 3, 10, 200] {
+            // a random tree with every edge twice, all degrees are even
+            let mut graph = SparseGraph::new(n);
+            let mut edges = Vec::new();
+            for x in 1..n {
+                let parent = rng.gen_range(0, x as usize) as i32;
+                for _ in 0..2 {
+                    graph.add_edge(parent, x);
+                    edges.push((parent, x));
+                }
+            }
+            assert_euler_circuit(&graph, &edges, 0);
+            assert_euler_circuit(&graph, &edges, n - 1);
+        }
+    }
+
+    #[test]
+    fn tree_plus_matching() {
+        // a tree in which every node has odd degree, then all nodes matched,
+        // with (0, 1) parallel to a tree edge
+        let mut edges = vec![(0, 1), (1, 2), (1, 3), (3, 4), (3, 5), (5, 6), (5, 7)];
+        edges.extend([(0, 1), (2, 3), (4, 5), (7, 6)]);
+        let mut graph = SparseGraph::new(8);
+        for &(x, y) in &edges {
+            graph.add_edge(x, y);
+        }
+        assert!(graph.adjacency_list.iter().all(|neighbors| neighbors.len() % 2 == 0));
+        for start in 0..8 {
+            assert_euler_circuit(&graph, &edges, start);
+        }
+
+        let tour = shortcut(&euler_circuit(&graph, 0), 8);
+        let mut sorted = tour.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..8).collect::<Vec<i32>>());
+        assert_eq!(tour[0], 0);
+    }
+
+    #[test]
+    fn best_occurrence_is_optimal() {
+        let mut rng = SimpleRng::new(4);
+        for n in [3, 6, 8, 9] {
+            let points: Vec<(f64, f64)> = (0..n).map(|_| (rng.next_f64() * 100.0, rng.next_f64() * 100.0)).collect();
+            let graph = Graph::with_metric(&points, Metric::Euc2d);
+            // a doubled random tree, and a doubled cycle in which every node
+            // is repeated
+            let mut tree = SparseGraph::new(n);
+            let mut cycle = SparseGraph::new(n);
+            for x in 0..n {
+                let parent = rng.gen_range(0, x.max(1) as usize) as i32;
+                for _ in 0..2 {
+                    if x > 0 {
+                        tree.add_edge(parent, x);
+                    }
+                    cycle.add_edge(x, (x + 1) % n);
+                }
+            }
+            for multigraph in [tree, cycle] {
+                let circuit = euler_circuit(&multigraph, 0);
+                let nodes = &circuit[..circuit.len() - 1];
+                let mut occurrences = vec![Vec::new(); n as usize];
+                for (i, &x) in nodes.iter().enumerate() {
+                    occurrences[x as usize].push(i);
+                }
+                // all ways to keep one visit of every node
+                let mut choice = vec![0; n as usize];
+                let mut shortest = i64::MAX;
+                loop {
+                    let kept: Vec<usize> = (0..n as usize).map(|x| occurrences[x][choice[x]]).collect();
+                    let tour: Vec<i32> = (0..nodes.len()).filter(|i| kept.contains(i)).map(|i| nodes[i]).collect();
+                    shortest = shortest.min(tour_length(&graph, &tour));
+                    let Some(x) = (0..n as usize).find(|&x| choice[x] + 1 < occurrences[x].len()) else { break };
+                    choice[x] += 1;
+                    choice[..x].fill(0);
+                }
+                let tour = best_occurrence(&graph, &circuit);
+                let mut sorted = tour.clone();
+                sorted.sort();
+                assert_eq!(sorted, (0..n).collect::<Vec<i32>>());
+                assert_eq!(tour_length(&graph, &tour), shortest);
+            }
+        }
+    }
+
+    #[test]
+    fn shortcut_policies_gain() {
+        let mut rng = SimpleRng::new(6);
+        let points: Vec<(f64, f64)> = (0..150).map(|_| (rng.next_f64() * 1000.0, rng.next_f64() * 1000.0)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        // the doubled spanning tree, as in the tree doubling heuristic
+        let tree = graph.get_min_spanning_tree();
+        let mut multigraph = SparseGraph::new(150);
+        for x in 0..150 {
+            for &y in &tree.adjacency_list[x as usize] {
+                multigraph.add_edge(x, y);
+            }
+        }
+
+        let first_visit = shortcut_tour(&graph, &multigraph, Shortcut::FirstVisit);
+        assert_eq!(first_visit.gain, 0);
+        assert_eq!(first_visit.tour, shortcut(&euler_circuit(&multigraph, 0), 150));
+        let best_occurrence = shortcut_tour(&graph, &multigraph, Shortcut::BestOccurrence);
+        let multi_start = shortcut_tour(&graph, &multigraph, Shortcut::MultiStart { tries: 10, seed: 1 });
+        assert!(best_occurrence.gain > 0);
+        assert!(multi_start.gain >= best_occurrence.gain);
+        for result in [first_visit, best_occurrence, multi_start] {
+            let length = tour_length(&graph, &result.tour);
+            assert_eq!(result.length, length);
+            assert_eq!(result.gain, tour_length(&graph, &shortcut(&euler_circuit(&multigraph, 0), 150)) - length);
+            let mut sorted = result.tour;
+            sorted.sort();
+            assert_eq!(sorted, (0..150).collect::<Vec<i32>>());
+        }
+    }
+}
+
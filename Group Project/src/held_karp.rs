@@ -0,0 +1,115 @@
+// Exact solver for small instances: the Held–Karp dynamic program over
+// subsets. The shortest path from node 0 through a set of nodes ending at
+// one of them extends the shortest paths through the set without it.
+// O(2^n n^2) time and O(2^n n) memory, fine up to about 20 nodes.
+
+use crate::distance::DistanceOracle;
+
+// the table of 2^(n - 1) (n - 1) path lengths takes 80 MB at this size
+pub const MAX_NODES: usize = 20;
+
+// An optimal tour, starting at node 0.
+pub fn exact_tour<D: DistanceOracle>(graph: &D) -> Vec<i32> {
+    let n = graph.num_nodes();
+    assert!(n <= MAX_NODES, "the exact solver handles at most {} nodes", MAX_NODES);
+    if n <= 3 {
+        // every order is the same cycle
+        return (0..n as i32).collect();
+    }
+
+    // node 0 is the start, node j + 1 is bit j of the sets
+    let m = n - 1;
+    let distance: Vec<i64> = (0..m * m)
+        .map(|i| if i / m == i % m { 0 } else { graph.get_edge((i / m + 1) as i32, (i % m + 1) as i32) as i64 })
+        .collect();
+    let from_start: Vec<i64> = (0..m).map(|j| graph.get_edge(0, j as i32 + 1) as i64).collect();
+    let full = (1 << m) - 1;
+    // path[set * m + j]: shortest path from node 0 through set ending at j
+    let mut path = vec![i64::MAX; (full + 1) * m];
+    for j in 0..m {
+        path[(1 << j) * m + j] = from_start[j];
+    }
+    // supersets are larger numbers, so they come later
+    for set in 1..full {
+        for j in (0..m).filter(|&j| set & 1 << j != 0) {
+            let length = path[set * m + j];
+            if length == i64::MAX {
+                continue;
+            }
+            for k in (0..m).filter(|&k| set & 1 << k == 0) {
+                let next = &mut path[(set | 1 << k) * m + k];
+                *next = (*next).min(length + distance[j * m + k]);
+            }
+        }
+    }
+    let mut last = (0..m).min_by_key(|&j| path[full * m + j] + from_start[j]).unwrap();
+
+    // walk back through the predecessors that give the optimal lengths
+    let mut tour = vec![0; n];
+    let mut set = full;
+    for position in (1..n).rev() {
+        tour[position] = last as i32 + 1;
+        let before = set & !(1 << last);
+        if before != 0 {
+            let length = path[set * m + last];
+            last = (0..m)
+                .find(|&k| before & 1 << k != 0 && path[before * m + k] + distance[k * m + last] == length)
+                .unwrap();
+        }
+        set = before;
+    }
+    tour
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use crate::utils::{Graph, SimpleRng, tour_length};
+
+    // shortest tour by trying all orders after node 0
+    fn brute_force(graph: &Graph, tour: &mut Vec<i32>, used: &mut [bool]) -> i64 {
+        let n = graph.num_nodes();
+        if tour.len() == n {
+            return tour_length(graph, tour);
+        }
+        let mut best = i64::MAX;
+        for y in 1..n {
+            if !used[y] {
+                used[y] = true;
+                tour.push(y as i32);
+                best = best.min(brute_force(graph, tour, used));
+                tour.pop();
+                used[y] = false;
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = SimpleRng::new(17);
+        for n in 1..=9 {
+            let points: Vec<(f64, f64)> = (0..n).map(|_| (rng.next_f64() * 1000.0, rng.next_f64() * 1000.0)).collect();
+            let graph = Graph::with_metric(&points, Metric::Euc2d);
+            let tour = exact_tour(&graph);
+            let mut sorted = tour.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..n as i32).collect::<Vec<i32>>());
+            assert_eq!(tour_length(&graph, &tour), brute_force(&graph, &mut vec![0], &mut vec![false; n]));
+        }
+    }
+
+    #[test]
+    fn circle_in_order() {
+        // the points of a circle in shuffled order, the optimum goes around
+        let mut order: Vec<usize> = (0..MAX_NODES).collect();
+        SimpleRng::new(4).shuffle(&mut order);
+        let angle = |i: usize| i as f64 * std::f64::consts::TAU / MAX_NODES as f64;
+        let points: Vec<(f64, f64)> = order.iter().map(|&i| (1000.0 * angle(i).cos(), 1000.0 * angle(i).sin())).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let tour = exact_tour(&graph);
+        let around: Vec<i32> = (0..MAX_NODES).map(|i| order.iter().position(|&j| j == i).unwrap() as i32).collect();
+        assert_eq!(tour_length(&graph, &tour), tour_length(&graph, &around));
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::Instant;
+use crate::distance::DistanceOracle;
+use crate::lin_kernighan_opt::Lin;
+use crate::neighbors::CandidateList;
+use crate::tour::DontLookBits;
+use crate::utils::SimpleRng;
+
+// longest segment moved by a kick; short segments keep the kick local
+const MAX_KICK_SEGMENT: usize = 50;
+
+// Iterated local search: Lin-Kernighan until it converges, then repeatedly
+// kick the best tour with a double bridge on two short neighbouring segments,
+// repair it with Lin-Kernighan started from the endpoints of the changed
+// edges and keep the result only if it is shorter. Otherwise the repair and
+// the kick are undone, which costs as much as they did, not O(n). Runs until
+// the deadline.
+pub fn iterated_local_search<D: DistanceOracle>(graph: &D, candidates: &CandidateList, tour: Vec<i32>, seed: u64, start_time: Instant, max_processing_ms: u128) -> Vec<i32> {
+    let n = tour.len();
+    let mut lin = Lin::new(tour, graph, candidates);
+    lin.execute(start_time, max_processing_ms);
+    if n < 8 {
+        return lin.tour.into_vec();
+    }
+
+    let mut rng = SimpleRng::new(seed);
+    let mut queue = DontLookBits::empty(n);
+    let max_segment = MAX_KICK_SEGMENT.min((n - 2) / 2);
+    while start_time.elapsed().as_millis() < max_processing_ms {
+        let from = rng.gen_range(0, n) as i32;
+        let len_b = rng.gen_range(1, max_segment + 1);
+        let len_c = rng.gen_range(1, max_segment + 1);
+
+        // A B C D -> A C B D changes the edges (a, b1), (b2, c1) and (c2, d)
+        let tour = &lin.tour;
+        let start = tour.position(from);
+        let at = |k: usize| tour.order()[(start + k) % n];
+        let (a, b1, b2) = (tour.prev(from), from, at(len_b - 1));
+        let (c1, c2, d) = (at(len_b), at(len_b + len_c - 1), at(len_b + len_c));
+        let kick = graph.get_edge(a, c1) as i64 + graph.get_edge(c2, b1) as i64 + graph.get_edge(b2, d) as i64
+            - graph.get_edge(a, b1) as i64 - graph.get_edge(b2, c1) as i64 - graph.get_edge(c2, d) as i64;
+
+        lin.tour.double_bridge(from, len_b, len_c);
+        for x in [a, b1, b2, c1, c2, d] {
+            queue.push(x);
+        }
+        let gain = lin.run(&mut queue, start_time, max_processing_ms);
+        if gain > kick {
+            lin.commit();
+            continue;
+        }
+        lin.rollback();
+        // A C B D back to A B C D, in whichever direction the flips left it
+        if lin.tour.next(a) == c1 {
+            lin.tour.double_bridge(c1, len_c, len_b);
+        } else {
+            lin.tour.double_bridge(b2, len_b, len_c);
+        }
+    }
+    lin.tour.into_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use crate::utils::{tour_length, Graph};
+
+    #[test]
+    fn not_worse_than_lin_kernighan() {
+        let mut rng = SimpleRng::new(11);
+        let points: Vec<(f64, f64)> = (0..300).map(|_| (rng.next_f64() * 1000.0, rng.next_f64() * 1000.0)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let candidates = CandidateList::new(&graph, 8);
+        let tour: Vec<i32> = (0..300).collect();
+
+        let mut lin = Lin::new(tour.clone(), &graph, &candidates);
+        lin.execute(Instant::now(), 10_000);
+        let local_optimum = tour_length(&graph, lin.tour.order());
+
+        let tour = iterated_local_search(&graph, &candidates, tour, 1, Instant::now(), 200);
+        assert!(tour_length(&graph, &tour) <= local_optimum);
+        let mut sorted = tour.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..300).collect::<Vec<i32>>());
+    }
+}
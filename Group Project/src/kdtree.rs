@@ -0,0 +1,227 @@
+// 2-d tree over points for Euclidean nearest neighbor queries.
+//
+// The tree is stored implicitly: the point ids are permuted such that the
+// range lo..hi is split at mid = (lo + hi) / 2, the node of that range, along
+// x on even and y on odd depths. Points can be removed; every node counts the
+// points still alive in its range so that empty subtrees are skipped.
+//
+// Only depends on std so that the single-file solutions can include it.
+
+pub struct KdTree {
+    points: Vec<(f64, f64)>,
+    // point ids in tree order
+    ids: Vec<usize>,
+    // number of alive points in the range whose node is at this position
+    alive_count: Vec<usize>,
+    alive: Vec<bool>,
+    // position of every point id in ids
+    position: Vec<usize>,
+}
+
+fn squared_distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0) * (a.0 - b.0) + (a.1 - b.1) * (a.1 - b.1)
+}
+
+fn coordinate(p: (f64, f64), depth: usize) -> f64 {
+    match depth % 2 {
+        0 => p.0,
+        _ => p.1,
+    }
+}
+
+impl KdTree {
+    pub fn new(points: &[(f64, f64)]) -> KdTree {
+        let n = points.len();
+        let mut tree = KdTree {
+            points: points.to_vec(),
+            ids: (0..n).collect(),
+            alive_count: vec![0; n],
+            alive: vec![true; n],
+            position: vec![0; n],
+        };
+        tree.build(0, n, 0);
+        for (i, &id) in tree.ids.iter().enumerate() {
+            tree.position[id] = i;
+        }
+        tree
+    }
+
+    fn build(&mut self, lo: usize, hi: usize, depth: usize) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let points = &self.points;
+        self.ids[lo..hi].select_nth_unstable_by(mid - lo, |&a, &b| {
+            coordinate(points[a], depth).total_cmp(&coordinate(points[b], depth))
+        });
+        self.alive_count[mid] = hi - lo;
+        self.build(lo, mid, depth + 1);
+        self.build(mid + 1, hi, depth + 1);
+    }
+
+    // removes the point from all further queries
+    pub fn remove(&mut self, id: usize) {
+        if !self.alive[id] {
+            return;
+        }
+        self.alive[id] = false;
+        let target = self.position[id];
+        let (mut lo, mut hi) = (0, self.ids.len());
+        loop {
+            let mid = (lo + hi) / 2;
+            self.alive_count[mid] -= 1;
+            if target == mid {
+                break;
+            } else if target < mid {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+    }
+
+    // the closest alive point
+    pub fn nearest(&self, p: (f64, f64)) -> Option<usize> {
+        self.k_nearest(p, 1).first().copied()
+    }
+
+    // the k closest alive points, closest first
+    pub fn k_nearest(&self, p: (f64, f64), k: usize) -> Vec<usize> {
+        // (squared distance, id), kept sorted; k is small
+        let mut best: Vec<(f64, usize)> = Vec::with_capacity(k + 1);
+        if k > 0 {
+            self.search_k(p, k, 0, self.ids.len(), 0, &mut best);
+        }
+        best.into_iter().map(|(_, id)| id).collect()
+    }
+
+    fn search_k(&self, p: (f64, f64), k: usize, lo: usize, hi: usize, depth: usize, best: &mut Vec<(f64, usize)>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        if self.alive_count[mid] == 0 {
+            return;
+        }
+        let id = self.ids[mid];
+        if self.alive[id] {
+            let d = squared_distance(p, self.points[id]);
+            if best.len() < k || d < best[best.len() - 1].0 {
+                let at = best.partition_point(|&(e, _)| e <= d);
+                best.insert(at, (d, id));
+                best.truncate(k);
+            }
+        }
+        let gap = coordinate(p, depth) - coordinate(self.points[id], depth);
+        let (near, far) = if gap < 0.0 { ((lo, mid), (mid + 1, hi)) } else { ((mid + 1, hi), (lo, mid)) };
+        self.search_k(p, k, near.0, near.1, depth + 1, best);
+        // the other side can only help if the splitting line is closer than the k-th best
+        if best.len() < k || gap * gap < best[best.len() - 1].0 {
+            self.search_k(p, k, far.0, far.1, depth + 1, best);
+        }
+    }
+
+    // all alive points within distance r, in no particular order
+    #[allow(dead_code)] // for insertion heuristics, the solvers do not use it yet
+    pub fn within_radius(&self, p: (f64, f64), r: f64) -> Vec<usize> {
+        let mut result = Vec::new();
+        self.search_radius(p, r * r, 0, self.ids.len(), 0, &mut result);
+        result
+    }
+
+    fn search_radius(&self, p: (f64, f64), r2: f64, lo: usize, hi: usize, depth: usize, result: &mut Vec<usize>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        if self.alive_count[mid] == 0 {
+            return;
+        }
+        let id = self.ids[mid];
+        if self.alive[id] && squared_distance(p, self.points[id]) <= r2 {
+            result.push(id);
+        }
+        let gap = coordinate(p, depth) - coordinate(self.points[id], depth);
+        if gap < 0.0 || gap * gap <= r2 {
+            self.search_radius(p, r2, lo, mid, depth + 1, result);
+        }
+        if gap >= 0.0 || gap * gap <= r2 {
+            self.search_radius(p, r2, mid + 1, hi, depth + 1, result);
+        }
+    }
+}
+
+// Nearest neighbor tour starting at point 0, every step picks the closest
+// unvisited point.
+pub fn nearest_neighbor_tour(points: &[(f64, f64)]) -> Vec<usize> {
+    let mut tree = KdTree::new(points);
+    let mut tour = Vec::with_capacity(points.len());
+    let mut current = 0;
+    while !points.is_empty() {
+        tour.push(current);
+        tree.remove(current);
+        match tree.nearest(points[current]) {
+            Some(next) => current = next,
+            None => break,
+        }
+    }
+    tour
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_points() -> Vec<(f64, f64)> {
+        (0..100).map(|i| ((i % 10) as f64, (i / 10) as f64 * 1.5)).collect()
+    }
+
+    fn brute_force(points: &[(f64, f64)], alive: &[bool], p: (f64, f64), k: usize) -> Vec<f64> {
+        let mut d: Vec<f64> = (0..points.len()).filter(|&i| alive[i]).map(|i| squared_distance(p, points[i])).collect();
+        d.sort_by(f64::total_cmp);
+        d.truncate(k);
+        d
+    }
+
+    #[test]
+    fn k_nearest_matches_brute_force() {
+        let points = grid_points();
+        let mut tree = KdTree::new(&points);
+        let mut alive = vec![true; points.len()];
+        for removed in [5, 17, 55, 56, 99] {
+            tree.remove(removed);
+            alive[removed] = false;
+        }
+        assert_eq!(tree.k_nearest((0.0, 0.0), 100).len(), 95);
+        for p in [(0.0, 0.0), (4.3, 7.1), (12.0, -3.0), (5.0, 7.5)] {
+            let found: Vec<f64> = tree.k_nearest(p, 7).iter().map(|&i| squared_distance(p, points[i])).collect();
+            assert_eq!(found, brute_force(&points, &alive, p, 7));
+        }
+    }
+
+    #[test]
+    fn radius_query() {
+        let points = grid_points();
+        let mut tree = KdTree::new(&points);
+        let mut found = tree.within_radius((3.0, 3.0), 1.5);
+        found.sort();
+        assert_eq!(found, vec![13, 22, 23, 24, 33]);
+        tree.remove(23);
+        assert_eq!(tree.within_radius((3.0, 3.0), 1.5).len(), 4);
+    }
+
+    #[test]
+    fn nearest_neighbor_visits_all() {
+        let points = grid_points();
+        let mut tour = nearest_neighbor_tour(&points);
+        assert_eq!(&tour[..3], &[0, 1, 2]);
+        tour.sort();
+        assert_eq!(tour, (0..100).collect::<Vec<usize>>());
+        let mut tree = KdTree::new(&points);
+        for i in 0..100 {
+            tree.remove(i);
+        }
+        assert_eq!(tree.nearest((1.0, 1.0)), None);
+    }
+}
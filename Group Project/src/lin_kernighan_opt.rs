@@ -1,70 +1,236 @@
-mod lin_kernighan_opt {
-    use std::mem::swap;
-    use crate::utils::Graph;
-
-    pub struct Lin<'a> {
-        pub stack_2_opt_moves: Vec<(i32,i32)>,
-        pub tour: Vec<i32>,
-        pub graph: &'a Graph,
+use std::time::Instant;
+use crate::distance::DistanceOracle;
+use crate::neighbors::CandidateList;
+use crate::tour::{DontLookBits, Tour};
+
+// maximum number of 2-opt moves in one sequential exchange
+const MAX_DEPTH: usize = 50;
+// number of alternatives tried for the first levels, deeper levels only try the best one
+const BREADTH: [usize; 2] = [5, 3];
+
+// Variable-depth Lin-Kernighan search where every step of a sequential
+// exchange is a 2-opt move. Starting from the edge (t1, t2), a step adds the
+// edge (t2, t3) for a candidate t3, removes (t3, t4) and the tour is closed
+// with (t4, t1). The chain goes on as long as the gain of the added and
+// removed edges stays positive; the best closed tour along the chain is kept,
+// everything after it is undone through the 2-opt move stack.
+pub struct Lin<'a, D> {
+    // (t1, t2, t3, t4): the edges (t1, t2) and (t3, t4) were replaced by (t1, t3) and (t2, t4)
+    pub stack_2_opt_moves: Vec<(i32, i32, i32, i32)>,
+    pub tour: Tour,
+    pub graph: &'a D,
+    candidates: &'a CandidateList,
+    // edges added in the current chain; they must not be removed again
+    added: Vec<(i32, i32)>,
+    best_gain: i32,
+    best_depth: usize,
+    // the moves of the improvements since the last commit, for rollback
+    applied: Vec<(i32, i32, i32, i32)>,
+}
+
+impl<'a, D: DistanceOracle> Lin<'a, D> {
+    pub fn new(tour: Vec<i32>, graph: &'a D, candidates: &'a CandidateList) -> Lin<'a, D> {
+        Lin {
+            stack_2_opt_moves: Vec::new(),
+            tour: Tour::new(tour),
+            graph,
+            candidates,
+            added: Vec::new(),
+            best_gain: 0,
+            best_depth: 0,
+            applied: Vec::new(),
+        }
+    }
+
+    // Run until no node can start an improving exchange or the time is up.
+    pub fn execute(&mut self, start_time: Instant, max_processing_ms: u128) {
+        let mut queue = DontLookBits::new(&self.tour);
+        self.run(&mut queue, start_time, max_processing_ms);
+        self.commit();
     }
 
-    impl Lin {
-        pub fn new(tour: Vec<i32>, graph: &Graph) -> Lin {
-            Lin {
-                stack_2_opt_moves: Vec::new(),
-                tour,
-                graph
+    // Same as execute, but only the queued nodes are tried as t1.
+    // Returns by how much the tour got shorter; rollback takes it back.
+    pub fn run(&mut self, queue: &mut DontLookBits, start_time: Instant, max_processing_ms: u128) -> i64 {
+        let mut gain = 0;
+        if self.tour.len() < 5 {
+            return gain;
+        }
+        while start_time.elapsed().as_millis() < max_processing_ms {
+            let t1 = match queue.pop() {
+                Some(t1) => t1,
+                None => break,
+            };
+            if self.improve(t1) {
+                gain += self.best_gain as i64;
+                for &(a, b, c, d) in &self.stack_2_opt_moves {
+                    for x in [a, b, c, d] {
+                        queue.push(x);
+                    }
+                }
+                self.applied.append(&mut self.stack_2_opt_moves);
             }
         }
+        gain
+    }
 
-
-        pub fn execute() {
-
+    // Try both tour neighbors of t1 as t2. Returns whether the tour got shorter;
+    // the applied moves are left on the stack.
+    fn improve(&mut self, t1: i32) -> bool {
+        for t2 in [self.successor(t1), self.predecessor(t1)] {
+            self.best_gain = 0;
+            self.best_depth = 0;
+            self.added.clear();
+            let gain = self.graph.get_edge(t1, t2);
+            if self.step(0, t1, t2, gain) {
+                while self.stack_2_opt_moves.len() > self.best_depth {
+                    self.undo();
+                }
+                return true;
+            }
         }
+        false
+    }
 
-        pub fn predecessor(&self, node: i32) -> i32 {
-            let index = self.tour.iter().position(|&r| r == node).unwrap();
-
-            return if index == 0 {self.tour[self.tour.len() - 1]} else {self.tour[index - 1]};
+    // gain is the length of the removed edges minus the added ones, not
+    // counting the closing edge (t2, t1)
+    fn step(&mut self, level: usize, t1: i32, t2: i32, gain: i32) -> bool {
+        // t1 follows t2 in this direction, so does t4 after t3
+        let forward = self.successor(t2) == t1;
+
+        let mut alternatives = Vec::new();
+        for &t3 in self.candidates.get(t2) {
+            let g1 = gain - self.graph.get_edge(t2, t3);
+            if g1 <= 0 {
+                break;
+            }
+            if t3 == t1 || t3 == self.successor(t2) || t3 == self.predecessor(t2) {
+                continue;
+            }
+            let t4 = self.tour.succ(t3, forward);
+            if self.added.contains(&(t3.min(t4), t3.max(t4))) {
+                continue;
+            }
+            alternatives.push((g1 + self.graph.get_edge(t3, t4), t3, t4));
         }
+        alternatives.sort_by_key(|&(g2, _, _)| std::cmp::Reverse(g2));
+        alternatives.truncate(if level < BREADTH.len() { BREADTH[level] } else { 1 });
 
-        pub fn successor(&self, node:i32)->i32 {
-            let index = self.tour.iter().position(|&r| r == node).unwrap();
+        for (g2, t3, t4) in alternatives {
+            self.swap(t2, t1, t3, t4);
+            self.added.push((t2.min(t3), t2.max(t3)));
 
-            return if index == self.tour.len() - 1 {self.tour[0]} else {self.tour[index + 1]};
-        }
+            let closed_gain = g2 - self.graph.get_edge(t4, t1);
+            if closed_gain > self.best_gain {
+                self.best_gain = closed_gain;
+                self.best_depth = self.stack_2_opt_moves.len();
+            }
+            if level + 1 < MAX_DEPTH && self.step(level + 1, t1, t4, g2) {
+                return true;
+            }
+            if self.best_gain > 0 {
+                return true;
+            }
 
-        pub fn between(&self, node: i32, before: i32, after: i32, direction: bool) -> bool {
-            false
+            self.added.pop();
+            self.undo();
         }
+        false
+    }
 
-        pub fn has_positive_gain(&self, (o1,o2): (i32, i32), (n1, n2): (i32, i32)) -> bool {
-            self.graph.get_edge(o1,o2) - self.graph.get_edge(n1,n2) > 0
-        }
+    pub fn predecessor(&self, node: i32) -> i32 {
+        self.tour.prev(node)
+    }
 
-        pub fn move_5_opt() {
-            // todo max 5 2-opt moves
-        }
+    pub fn successor(&self, node:i32)->i32 {
+        self.tour.next(node)
+    }
 
-        pub fn move_4_opt() {
-            // todo max 3 2-opt moves
-        }
+    // one 2-opt move, recorded so that it can be undone
+    pub fn swap(&mut self, t1: i32, t2: i32, t3: i32, t4: i32) {
+        self.tour.move_2opt(t1, t2, t3, t4);
+        self.stack_2_opt_moves.push((t1, t2, t3, t4));
+    }
 
-        pub fn move_3_opt() {
-            // todo max 3 2-opt moves
-        }
+    // Keeps the improvements so far, a rollback stops here.
+    pub fn commit(&mut self) {
+        self.applied.clear();
+    }
 
-        pub fn swap(self, i: i32, j:i32) {
-            // todo max 1 2-opt move
+    // Reverts all improvements since the last commit, in O(changed path
+    // lengths) rather than by copying the tour.
+    pub fn rollback(&mut self) {
+        while let Some((t1, t2, t3, t4)) = self.applied.pop() {
+            self.tour.move_2opt(t1, t3, t2, t4);
         }
+    }
 
-
-        pub fn undo(&mut self) {
-            while let (x,y) = self.stack_2_opt_moves.pop().unwrap() {
-                self.swap(x,y);
-            }
+    // reverts the last recorded 2-opt move
+    pub fn undo(&mut self) {
+        if let Some((t1, t2, t3, t4)) = self.stack_2_opt_moves.pop() {
+            // t3 now follows t1 and t4 follows t2
+            self.tour.move_2opt(t1, t3, t2, t4);
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use crate::utils::{tour_length, Graph, SimpleRng};
+
+    fn random_instance(n: usize, seed: u64) -> (Graph, Vec<i32>) {
+        let mut rng = SimpleRng::new(seed);
+        let points: Vec<(f64, f64)> = (0..n).map(|_| (rng.next_f64() * 1000.0, rng.next_f64() * 1000.0)).collect();
+        let mut tour: Vec<i32> = (0..n as i32).collect();
+        rng.shuffle(&mut tour);
+        (Graph::with_metric(&points, Metric::Euc2d), tour)
+    }
+
+    #[test]
+    fn improves_random_tour() {
+        let (graph, tour) = random_instance(200, 7);
+        let candidates = CandidateList::new(&graph, 8);
+        let before = tour_length(&graph, &tour);
+        let mut lin = Lin::new(tour, &graph, &candidates);
+        lin.execute(Instant::now(), 10_000);
+        let tour = lin.tour.into_vec();
+        assert!(tour_length(&graph, &tour) < before);
+        let mut sorted = tour.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..200).collect::<Vec<i32>>());
+    }
 
+    #[test]
+    fn undo_restores_tour() {
+        let (graph, tour) = random_instance(10, 3);
+        let candidates = CandidateList::new(&graph, 4);
+        let mut lin = Lin::new(tour.clone(), &graph, &candidates);
+        let (t1, t2) = (tour[0], tour[1]);
+        let (t3, t4) = (tour[5], tour[6]);
+        lin.swap(t1, t2, t3, t4);
+        assert!(lin.successor(t1) == t3 || lin.predecessor(t1) == t3);
+        lin.undo();
+        assert_eq!(tour_length(&graph, lin.tour.order()), tour_length(&graph, &tour));
+        assert!(lin.stack_2_opt_moves.is_empty());
+        assert!(lin.successor(t1) == t2 || lin.predecessor(t1) == t2);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn rollback_restores_edges() {
+        let (graph, tour) = random_instance(100, 5);
+        let candidates = CandidateList::new(&graph, 6);
+        let mut lin = Lin::new(tour.clone(), &graph, &candidates);
+        let mut queue = DontLookBits::new(&lin.tour);
+        assert!(lin.run(&mut queue, Instant::now(), 10_000) > 0);
+        lin.rollback();
+        let edges = |order: &[i32]| {
+            let mut edges: Vec<(i32, i32)> = (0..order.len()).map(|i| (order[i], order[(i + 1) % order.len()]))
+                .map(|(x, y)| (x.min(y), x.max(y))).collect();
+            edges.sort();
+            edges
+        };
+        assert_eq!(edges(lin.tour.order()), edges(&tour));
+    }
+}
@@ -1,53 +1,45 @@
+use crate::distance::DistanceOracle;
+use crate::neighbors::CandidateList;
 use crate::utils::SimpleRng;
-use crate::utils::Graph;
 use std::time::{Duration, Instant};
 
-pub struct Lin<'a> {
+// 2-opt and 3-opt with a short tabu list of the edges added last. Only the
+// moves whose first new edge joins a node to one of its candidates are
+// tried, so a pass is O(n k) 2-opt and O(n k^2) 3-opt moves.
+pub struct Lin<'a, D> {
     pub tour: Vec<i32>,
-    pub graph: &'a Graph,
+    pub graph: &'a D,
+    candidates: &'a CandidateList,
     tabu_list: Vec<(i32, i32)>,
     max_tabu_size: usize,
 }
 
-impl<'a> Lin<'a> {
-    pub fn new(tour: Vec<i32>, graph: &Graph) -> Lin {
-        let num_nodes = graph.num_nodes as usize;
+impl<'a, D: DistanceOracle> Lin<'a, D> {
+    pub fn new(tour: Vec<i32>, graph: &'a D, candidates: &'a CandidateList) -> Lin<'a, D> {
+        let num_nodes = graph.num_nodes();
         let max_tabu_size = std::cmp::max(5, num_nodes / 10);
         Lin {
             tour,
             graph,
+            candidates,
             tabu_list: Vec::new(),
             max_tabu_size,
         }
     }
 
-    // pub fn execute(&mut self) {
-    //     if self.tour.is_empty() {
-    //         let mut rng = SimpleRng::new(1698508300);
-    //         self.initialize_random_tour(&mut rng);
-    //     }
-
-    //     let duration = std::time::Duration::from_millis(1900);
-    //     let start_time = Instant::now();
-    //     let mut iteration = 0;
-
-    //     while start_time.elapsed() < duration {
-    //         self.two_opt(iteration, &start_time, &duration);
-    //         self.three_opt(iteration, &start_time, &duration);
-    //         iteration += 1;
-    //     }
-    // }
-
-    pub fn execute(&mut self) {
+    pub fn execute(&mut self, start_time: Instant, max_processing_ms: u128) {
         if self.tour.is_empty() {
             let mut rng = SimpleRng::new(1698508300);
             self.initialize_random_tour(&mut rng);
         }
+        if self.tour.len() < 5 {
+            return;
+        }
     
         let mut best_tour = self.tour.clone();
         let mut best_length = self.calculate_tour_length();
     
-        let total_duration = Duration::from_millis(1900);
+        let total_duration = Duration::from_millis(max_processing_ms as u64).saturating_sub(start_time.elapsed());
         let start_time = Instant::now();
     
         let mut iteration = 0;
@@ -57,9 +49,12 @@ impl<'a> Lin<'a> {
             let max_duration_start_time = Instant::now();
     
             let mut improved = false;
+            let candidates = self.candidates;
+            let mut pos = self.positions();
     
             'outer: for i in 0..self.tour.len() {
-                for j in i + 2..self.tour.len() {
+                let a = self.tour[i];
+                for &c in candidates.get(a) {
                     if start_time.elapsed() > total_duration {
                         self.tour = best_tour;
                         return;
@@ -67,6 +62,13 @@ impl<'a> Lin<'a> {
                     if max_duration_start_time.elapsed() > max_duration_per_operation {
                         break 'outer;
                     }
+                    // the new edge (tour[i], tour[j]) is a candidate one;
+                    // a may have moved with the last swap
+                    let (here, other) = (pos[a as usize], pos[c as usize]);
+                    let (i, j) = (here.min(other), here.max(other));
+                    if j < i + 2 {
+                        continue;
+                    }
                     if self.two_opt_swap(i, j) {
                         let new_length = self.calculate_tour_length();
                         if new_length < best_length {
@@ -74,15 +76,23 @@ impl<'a> Lin<'a> {
                             best_tour = self.tour.clone();
                             improved = true;
                         }
+                        pos = self.positions();
                     }
                 }
             }
 
             let max_duration_start_time = Instant::now();
+            let mut pos = self.positions();
     
             'outer2: for i in 0..self.tour.len() {
-                for j in i + 2..self.tour.len() {
-                    for k in j + 2..self.tour.len() {
+                for &c in candidates.get(self.tour[i]) {
+                    let other = pos[c as usize];
+                    let (i, j) = (i.min(other), i.max(other));
+                    if j < i + 2 || j + 1 == self.tour.len() {
+                        continue;
+                    }
+                    // and so is the second one, (tour[j + 1], tour[k])
+                    for &d in candidates.get(self.tour[j + 1]) {
                         if start_time.elapsed() > total_duration {
                             self.tour = best_tour;
                             return;
@@ -90,6 +100,10 @@ impl<'a> Lin<'a> {
                         if max_duration_start_time.elapsed() > max_duration_per_operation {
                             break 'outer2;
                         }
+                        let k = pos[d as usize];
+                        if k < j + 2 {
+                            continue;
+                        }
                         if self.three_opt_swap(i, j, k) {
                             let new_length = self.calculate_tour_length();
                             if new_length < best_length {
@@ -97,6 +111,9 @@ impl<'a> Lin<'a> {
                                 best_tour = self.tour.clone();
                                 improved = true;
                             }
+                            // the positions of i and j changed, go on with the next node
+                            pos = self.positions();
+                            break;
                         }
                     }
                 }
@@ -112,6 +129,14 @@ impl<'a> Lin<'a> {
         self.tour = best_tour;
     }
     
+    // position of every node in the tour
+    fn positions(&self) -> Vec<usize> {
+        let mut pos = vec![0; self.tour.len()];
+        for (i, &x) in self.tour.iter().enumerate() {
+            pos[x as usize] = i;
+        }
+        pos
+    }
 
     fn calculate_max_duration_per_operation(&self, iteration: usize, total_duration: Duration) -> Duration {
         let base_duration = total_duration / (self.tour.len() as u32 * 10);
@@ -120,33 +145,11 @@ impl<'a> Lin<'a> {
     }
 
     pub fn initialize_random_tour(&mut self, rng: &mut SimpleRng) {
-        let num_nodes = self.graph.num_nodes;
+        let num_nodes = self.graph.num_nodes() as i32;
         self.tour = (0..num_nodes).collect();
         rng.shuffle(&mut self.tour);
     }
 
-    pub fn two_opt(&mut self, iteration: usize, start_time: &Instant, duration: &Duration) {
-        let max_duration = self.max_duration_per_operation(iteration);
-        let mut improvement = true;
-
-        while improvement && start_time.elapsed() >= max_duration && start_time.elapsed() >= *duration {
-            improvement = false;
-            for i in 0..self.tour.len() - 1 {
-                for j in i + 2..self.tour.len() {
-                    if start_time.elapsed() >= max_duration && start_time.elapsed() >= *duration {
-                        return;
-                    }
-                    if j == self.tour.len() - 1 && i == 0 {
-                        continue;
-                    }
-                    if self.two_opt_swap(i, j) {
-                        improvement = true;
-                    }
-                }
-            }
-        }
-    }
-
     fn two_opt_swap(&mut self, i: usize, k: usize) -> bool {
         if self.tabu_list.contains(&(self.tour[i], self.tour[k])) {
             return false;
@@ -175,31 +178,6 @@ impl<'a> Lin<'a> {
         }
     }
 
-    pub fn three_opt(&mut self, iteration: usize, start_time: &Instant, duration: &Duration) {
-        let max_duration = self.max_duration_per_operation(iteration);
-        let mut improvement = true;
-
-        while improvement && start_time.elapsed() >= max_duration && start_time.elapsed() >= *duration {
-            improvement = false;
-            for i in 0..self.tour.len() - 2 {
-                for j in i + 2..self.tour.len() - 1 {
-                    for k in j + 2..self.tour.len() {
-                        if start_time.elapsed() >= max_duration && start_time.elapsed() >= *duration {
-                            return;
-                        }
-
-                        if k == self.tour.len() - 1 && i == 0 {
-                            continue;
-                        }
-                        if self.three_opt_swap(i, j, k) {
-                            improvement = true;
-                        }
-                    }
-                }
-            }
-        }
-    }
-
     fn three_opt_swap(&mut self, i: usize, j: usize, k: usize) -> bool {
         if self.tabu_list.contains(&(self.tour[i], self.tour[j])) || self.tabu_list.contains(&(self.tour[j], self.tour[k])) {
             return false;
@@ -238,12 +216,6 @@ impl<'a> Lin<'a> {
         }
     }
 
-    fn max_duration_per_operation(&self, iteration: usize) -> Duration {
-        let base_duration = Duration::from_millis(200);
-        let factor = self.graph.num_nodes as u32;
-        base_duration * (iteration as u32 + 1)
-    }
-
     fn try_reconnect(&mut self, best_tour: &mut Vec<i32>, best_length: &mut i32, i: usize, j: usize, k: usize, case: u8) {
         let mut new_tour = self.tour.clone();
     
@@ -298,7 +270,7 @@ impl<'a> Lin<'a> {
         length
     }
 
-    fn calculate_tour_length_for(&self, tour: &Vec<i32>) -> i32 {
+    fn calculate_tour_length_for(&self, tour: &[i32]) -> i32 {
         let mut length = 0;
         for i in 0..tour.len() - 1 {
             length += self.graph.get_edge(tour[i], tour[i + 1]);
@@ -306,4 +278,28 @@ impl<'a> Lin<'a> {
         length += self.graph.get_edge(tour[tour.len() - 1], tour[0]);
         length
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use crate::one_tree::alpha_candidates;
+    use crate::utils::{tour_length, Graph};
+
+    #[test]
+    fn improves_with_alpha_candidates() {
+        let mut rng = SimpleRng::new(11);
+        let points: Vec<(f64, f64)> = (0..150).map(|_| (rng.next_f64() * 1000.0, rng.next_f64() * 1000.0)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let mut tour: Vec<i32> = (0..150).collect();
+        rng.shuffle(&mut tour);
+        let candidates = alpha_candidates(&graph, None, 8);
+        let before = tour_length(&graph, &tour);
+        let mut lin = Lin::new(tour, &graph, &candidates);
+        lin.execute(Instant::now(), 10_000);
+        assert!(tour_length(&graph, &lin.tour) < before);
+        let mut sorted = lin.tour.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..150).collect::<Vec<i32>>());
+    }
+}
@@ -1,41 +1,99 @@
 mod utils;
-//mod blossom;
+mod distance;
+mod sparse_graph;
+mod blossom;
+mod bottleneck;
+mod one_tree;
+mod held_karp;
 mod mwmatching;
-// mod lin_kernighan_opt;
-// TODO always comment out before uploading solution
-//mod test;
+mod matching;
+mod tsplib;
+mod metric;
+mod neighbors;
+mod tour;
+mod lin_kernighan_opt;
+// only the tests run it, the Lin of lin_kernighan_opt.rs is faster
+#[cfg(test)]
+mod lin_kernighan_opt_c;
+mod iterated_local_search;
+mod simulated_annealing;
+mod delaunay;
+mod euler;
+mod kdtree;
+#[cfg(test)]
+mod test;
 
 
 use std::collections::HashMap;
-use std::io::{self};
+use std::env;
+use std::fs;
+use std::io::{self, Read};
 use std::time::Instant;
-use mwmatching::Matching;
+use metric::Metric;
+use distance::{DistanceOracle, FlatMatrix, LazyDistances};
+use neighbors::CandidateList;
+use delaunay::LazyDelaunay;
+use bottleneck::{bottleneck_tour, BOUND_NEIGHBORS};
+use one_tree::{CandidateSet, alpha_candidates, held_karp_bound};
+use held_karp::exact_tour;
+use euler::{Shortcut, shortcut_tour};
+use matching::{MatchingStrategy, perfect_matching};
+use iterated_local_search::iterated_local_search;
+use simulated_annealing::{Cooling, Schedule, simulated_annealing};
 // use log::info;
 //use blossom::{Vertex, WeightedGraph, AnnotatedGraph};
-use crate::utils::{Graph, three_opt, two_opt};
-use crate::utils::SparseGraph;
+use crate::utils::{Graph, or_opt, three_opt, tour_length, two_opt};
+#[cfg(test)]
+use crate::utils::DisjointSet;
+use crate::sparse_graph::SparseGraph;
 
-fn nearest_neighbor_tour(points: &Vec<(f64, f64)>) -> Vec<i32> {
-    let n = points.len();
-    let mut tour:Vec<i32> = vec![0; n];
-    let mut used = vec![false; n];
+// number of nearest neighbors the local search considers for every node
+const NUM_CANDIDATES: usize = 8;
+// up to this the full n x n FlatMatrix is used, it has the fastest lookups
+const MAX_FLAT_NODES: usize = 4_000;
+// above this even the triangular matrix of Graph takes too much memory and
+// the distances are computed on demand
+const MAX_DENSE_NODES: usize = 10_000;
+// seed of the kicks in the iterated local search and the shortcut circuits
+const SEED: u64 = 12345;
+// nearest neighbors of every odd degree node the sparse matchings consider
+const MATCHING_NEIGHBORS: usize = 10;
+// up to this the exact dynamic program solves the instance, in at most a
+// few hundred milliseconds
+const MAX_EXACT_NODES: usize = held_karp::MAX_NODES;
+// time limit of --bottleneck
+const BOTTLENECK_MS: u128 = 1900;
+// time for the subgradient optimization of --lower-bound, after the tour
+const LOWER_BOUND_MS: u128 = 2000;
+// time for the penalties of --candidates held-karp, out of the time limit
+const ALPHA_ASCENT_MS: u128 = 300;
+// matching used by christofidis unless --matching says otherwise; at
+// n = 1000 about 20 times faster than exact and mostly just as good
+const DEFAULT_MATCHING: MatchingStrategy = MatchingStrategy::SparseExact { k: MATCHING_NEIGHBORS };
+// Euler circuits tried by the multi start shortcut
+const SHORTCUT_TRIES: usize = 10;
 
-    used[0] = true;
-
-    for i in 1..n {
-        let mut best = None;
-        for j in 0..n {
-            if !used[j] && (best.is_none() || utils::euclidean_distance(points[tour[i-1] as usize], points[j]) < utils::euclidean_distance(points[tour[i-1] as usize], points[best.unwrap()])) {
-                best = Some(j);
-            }
+// exact k nearest neighbors unless the Delaunay graph already contains them,
+// or the alpha-nearest ones if --candidates asks for them
+fn candidate_list<D: DistanceOracle>(graph: &D, delaunay: &LazyDelaunay, options: &Options, tour: &[i32]) -> CandidateList {
+    match options.candidates {
+        Some(CandidateSet::Alpha { ascent_ms }) => {
+            // the tour is the target of the subgradient steps
+            let penalties = (ascent_ms > 0).then(|| held_karp_bound(graph, tour_length(graph, tour), Instant::now(), ascent_ms).penalties);
+            alpha_candidates(graph, penalties.as_deref(), NUM_CANDIDATES)
         }
-        tour[i] = best.unwrap() as i32;
-        used[tour[i] as usize] = true;
+        _ if delaunay.is_euclidean() => delaunay.get().candidate_list(NUM_CANDIDATES),
+        _ => CandidateList::new(graph, NUM_CANDIDATES),
     }
+}
 
-    tour
+// nearest by Euclidean distance, whatever the metric
+fn nearest_neighbor_tour(points: &[(f64, f64)]) -> Vec<i32> {
+    kdtree::nearest_neighbor_tour(points).into_iter().map(|x| x as i32).collect()
 }
 
+// only the tests compare against it
+#[cfg(test)]
 fn greedy_tour(graph: &Graph, optimize: bool) -> Vec<i32> {
     let start_time = Instant::now();
     let n = graph.num_nodes;
@@ -43,17 +101,22 @@ fn greedy_tour(graph: &Graph, optimize: bool) -> Vec<i32> {
     let mut sorted = graph.get_edges_sorted();
     let mut tour = vec![0; n as usize];
     let mut sparse_graph = SparseGraph::new(n);
+    let mut fragments = DisjointSet::new(n as usize);
 
-    while !sorted.is_empty() {
+    // grow paths until they form a single Hamiltonian path
+    let mut edge_count = 0;
+    while !sorted.is_empty() && edge_count < n - 1 {
         let (x, y) = sorted.pop().unwrap();
-        if sparse_graph.get_vertex_degree(x) < 2 && sparse_graph.get_vertex_degree(y) < 2 {
+        if sparse_graph.get_vertex_degree(x) < 2 && sparse_graph.get_vertex_degree(y) < 2 && fragments.union(x, y) {
             sparse_graph.add_edge(x, y);
-            let circle_length = sparse_graph.get_circle(x);
-            if circle_length >= 0 && circle_length < n {
-                sparse_graph.remove_edge(x, y);
-            }
+            edge_count += 1;
         }
     }
+    // close the path between its two ends
+    if n > 2 {
+        let ends: Vec<i32> = (0..n).filter(|&x| sparse_graph.get_vertex_degree(x) == 1).collect();
+        sparse_graph.add_edge(ends[0], ends[1]);
+    }
 
     // build tour from sparse graph
     tour[0] = 0;
@@ -66,38 +129,37 @@ fn greedy_tour(graph: &Graph, optimize: bool) -> Vec<i32> {
     if !optimize {
         return tour.clone();
     }
-    three_opt(graph, tour, start_time, 1950)
+    let candidates = CandidateList::new(graph, NUM_CANDIDATES);
+    let tour = or_opt(graph, &candidates, two_opt(graph, &candidates, tour, start_time, 1950), start_time, 1950);
+    three_opt(graph, &candidates, tour, start_time, 1950)
 }
 
-fn christofidis(graph: &Graph, optimize: bool) -> Vec<i32> {
-    let start_time= Instant::now();
-    if graph.num_nodes == 1 {
+// The matching strategy and the shortcut policy come from the command line;
+// the ones picked there report their result to stderr.
+fn christofidis<D: DistanceOracle>(graph: &D, delaunay: &LazyDelaunay, options: &Options, optimize: bool, start_time: Instant) -> Vec<i32> {
+    if graph.num_nodes() == 1 {
         return vec![0];
     }
     let mut prev_time = Instant::now();
-    let mut spanning_tree: SparseGraph = graph.get_min_spanning_tree();
+    // for Euclidean distances the minimum spanning tree is a subgraph of the Delaunay graph
+    let mut spanning_tree: SparseGraph = if delaunay.is_euclidean() {
+        delaunay.get().get_min_spanning_tree()
+    } else {
+        graph.get_min_spanning_tree()
+    };
     // info!("Spanning tree: {:?}", Instant::now() - prev_time);
 
     prev_time = Instant::now();
     let odd_degree_nodes = spanning_tree.adjacency_list.iter().
-        enumerate().filter(|(_, v)| v.len() % 2 == 1).map(|(i, _)| i).collect::<Vec<usize>>();
-
+        enumerate().filter(|(_, v)| v.len() % 2 == 1).map(|(i, _)| i as i32).collect::<Vec<i32>>();
 
-    let max = *graph.edges.iter().flat_map(|row| row.iter()).max().unwrap();
-    let mut edges = Vec::new();
-    for i in 0..odd_degree_nodes.len() {
-        for j in i+1..odd_degree_nodes.len() {
-            let length = graph.get_edge(odd_degree_nodes[i] as i32, odd_degree_nodes[j] as i32);
-            edges.push((i,j,-length + max))
-        }
+    let strategy = options.matching.unwrap_or(DEFAULT_MATCHING);
+    let matching = perfect_matching(graph, &odd_degree_nodes, strategy);
+    if options.matching.is_some() {
+        eprintln!("matching {:?}: weight {} in {:?}", strategy, matching.weight, matching.time);
     }
-
-    let mates = Matching::new(edges).solve();
-
-    for i in 0..mates.len() {
-        if mates[i] < usize::MAX && mates[i] > i {
-            spanning_tree.add_edge(odd_degree_nodes[i] as i32, odd_degree_nodes[mates[i]] as i32);
-        }
+    for &(x, y) in &matching.pairs {
+        spanning_tree.add_edge(x, y);
     }
 
     prev_time = Instant::now();
@@ -105,71 +167,184 @@ fn christofidis(graph: &Graph, optimize: bool) -> Vec<i32> {
     //     spanning_tree.add_edge(odd_degree_nodes[x] as i32,odd_degree_nodes[y] as i32);
     // }
 
-    // Euler Tour
-    let mut visited = vec![0; graph.num_nodes as usize];
-    let mut tour:Vec<i32> = Vec::new();
-    tour.push(0);
-    while spanning_tree.get_edge().is_some() {
-        let mut start = *tour.iter().find(|&&ele| spanning_tree.adjacency_list[ele as usize].len() != 0).unwrap();
-        let mut y = spanning_tree.adjacency_list[start as usize][0];
-        // if !tour.contains(&start) {
-        //     let copy = start;
-        //     start = y;
-        //     y = copy;
-        // }
-        let mut counter = 1;
-        let mut position = tour.iter().position(|&ele| ele == start).unwrap();
-        let mut x = start;
-        spanning_tree.remove_edge(x, y);
-        while y != start {
-            if visited[y as usize] != 1 {
-                tour.insert(position + counter, y);
-                visited[y as usize] = 1;
-                counter += 1;
-            }
-            if spanning_tree.adjacency_list[y as usize].len() != 0 {
-                (x,y) = (y, spanning_tree.adjacency_list[y as usize][0]);
-                spanning_tree.remove_edge(x, y);
-            }
-        }
+    // Euler tour, shortcut to the best visit of every node over a few circuits
+    let policy = options.shortcut.unwrap_or(Shortcut::MultiStart { tries: SHORTCUT_TRIES, seed: SEED });
+    let shortcut_result = shortcut_tour(graph, &spanning_tree, policy);
+    if options.shortcut.is_some() {
+        eprintln!("shortcut {:?}: length {}, {} shorter than first visits", policy, shortcut_result.length, shortcut_result.gain);
     }
+    let tour = shortcut_result.tour;
     // info!("Euler tour: {:?}", Instant::now() - prev_time);
     if !optimize {
         return tour.clone();
     }
-    three_opt(graph, two_opt(graph, tour, start_time, 1980), start_time, 1980)
+    let candidates = candidate_list(graph, delaunay, options, &tour);
+    let tour = or_opt(graph, &candidates, two_opt(graph, &candidates, tour, start_time, 1980), start_time, 1980);
+    iterated_local_search(graph, &candidates, tour, SEED, start_time, 1980)
 }
 
-fn main() {
-    // Vector to hold all 2D points
-    let mut points = Vec::new();
-
-    let stdin = io::stdin();
-    let mut num_vecs = String::new();
-    stdin.read_line(&mut num_vecs).expect("error");
-    let num = num_vecs.trim().parse().expect("error parsing number");
-    for _ in 0..num {
-        let mut line = String::new();
-        stdin.read_line(&mut line).unwrap();
-        let nums: Vec<f64> = line.split_whitespace()
-            .map(|num| num.parse().expect("error"))
-            .collect();
-
-        points.push((nums[0], nums[1]));
+// everything but the huge instances; graph is whichever matrix fits
+fn solve<D: DistanceOracle>(graph: &D, points: &[(f64, f64)], delaunay: &LazyDelaunay, warm_start: Option<Vec<i32>>, options: &Options, start_time: Instant) -> Vec<i32> {
+    if options.bottleneck {
+        // a short tour tends to have short edges, a good start for the search
+        let tour = warm_start.unwrap_or_else(|| christofidis(graph, delaunay, options, false, start_time));
+        let candidates = candidate_list(graph, delaunay, options, &tour);
+        // the lower bound needs the nearest neighbors whatever the candidates are
+        let nearest = if delaunay.is_euclidean() {
+            delaunay.get().candidate_list(BOUND_NEIGHBORS)
+        } else {
+            CandidateList::new(graph, BOUND_NEIGHBORS)
+        };
+        let result = bottleneck_tour(graph, &candidates, &nearest, tour, start_time, BOTTLENECK_MS);
+        eprintln!("bottleneck {}, lower bound {}", result.bottleneck, result.lower_bound);
+        return result.tour;
     }
+    let tour = match (warm_start, options.annealing) {
+        (tour, Some(cooling)) => {
+            let tour = tour.unwrap_or_else(|| nearest_neighbor_tour(points));
+            let candidates = candidate_list(graph, delaunay, options, &tour);
+            let tour = simulated_annealing(graph, &candidates, tour, &Schedule::new(cooling), SEED, start_time, 1900);
+            or_opt(graph, &candidates, two_opt(graph, &candidates, tour, start_time, 1980), start_time, 1980)
+        }
+        (Some(tour), None) => {
+            let candidates = candidate_list(graph, delaunay, options, &tour);
+            let tour = or_opt(graph, &candidates, two_opt(graph, &candidates, tour, start_time, 1980), start_time, 1980);
+            three_opt(graph, &candidates, tour, start_time, 1980)
+        }
+        // small enough to solve exactly, nothing left to improve
+        (None, None) if graph.num_nodes() <= MAX_EXACT_NODES => exact_tour(graph),
+        (None, None) => christofidis(graph, delaunay, options, true, start_time),
+    };
+    if options.lower_bound {
+        let length = tour_length(graph, &tour);
+        let result = held_karp_bound(graph, length, Instant::now(), LOWER_BOUND_MS);
+        eprintln!("length {}, lower bound {}, gap {:.2}% after {} 1-trees", length, result.bound, 100.0 * result.gap(length), result.iterations);
+        eprintln!("penalties {:?}", result.penalties);
+    }
+    tour
+}
+
+#[derive(Default)]
+struct Options {
+    // overrides the metric declared by the input
+    metric: Option<Metric>,
+    // TSPLIB .tour file the final tour is written to
+    tour_out: Option<String>,
+    // .tour file or Kattis index list to keep improving instead of running christofidis
+    warm_start: Option<String>,
+    // run simulated annealing with this cooling schedule instead of christofidis
+    annealing: Option<Cooling>,
+    // how christofidis shortcuts its Euler tour
+    shortcut: Option<Shortcut>,
+    // how christofidis matches the odd degree nodes
+    matching: Option<MatchingStrategy>,
+    // minimize the longest edge of the tour instead of its length
+    bottleneck: bool,
+    // report the Held–Karp lower bound and the gap of the tour to it
+    lower_bound: bool,
+    // how the local search picks its candidate edges
+    candidates: Option<CandidateSet>,
+}
+
+fn parse_args() -> Options {
+    let mut options = Options::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--metric" => {
+                let name = args.next().expect("--metric needs a value");
+                options.metric = Some(Metric::from_name(&name).expect("unknown metric"));
+            }
+            "--tour-out" => options.tour_out = Some(args.next().expect("--tour-out needs a path")),
+            "--warm-start" => options.warm_start = Some(args.next().expect("--warm-start needs a path")),
+            "--annealing" => {
+                let name = args.next().expect("--annealing needs a cooling schedule");
+                options.annealing = Some(Cooling::from_name(&name).expect("unknown cooling schedule"));
+            }
+            "--shortcut" => {
+                let name = args.next().expect("--shortcut needs a policy");
+                options.shortcut = Some(match name.as_str() {
+                    "first" => Shortcut::FirstVisit,
+                    "best" => Shortcut::BestOccurrence,
+                    "multi" => Shortcut::MultiStart { tries: SHORTCUT_TRIES, seed: SEED },
+                    _ => panic!("unknown shortcut policy {}", name),
+                });
+            }
+            "--matching" => {
+                let name = args.next().expect("--matching needs a strategy");
+                options.matching = Some(match name.as_str() {
+                    "exact" => MatchingStrategy::Exact,
+                    "sparse" => MatchingStrategy::SparseExact { k: MATCHING_NEIGHBORS },
+                    "greedy" => MatchingStrategy::GreedyTwoOpt { k: MATCHING_NEIGHBORS },
+                    _ => panic!("unknown matching strategy {}", name),
+                });
+            }
+            "--bottleneck" => options.bottleneck = true,
+            "--lower-bound" => options.lower_bound = true,
+            "--candidates" => {
+                let name = args.next().expect("--candidates needs a kind");
+                options.candidates = Some(match name.as_str() {
+                    "nearest" => CandidateSet::Nearest,
+                    "alpha" => CandidateSet::Alpha { ascent_ms: 0 },
+                    "held-karp" => CandidateSet::Alpha { ascent_ms: ALPHA_ASCENT_MS },
+                    _ => panic!("unknown candidate set {}", name),
+                });
+            }
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+    options
+}
+
+fn main() {
+    // all time limits count from here, reading the input included
+    let start_time = Instant::now();
+    let options = parse_args();
+
+    // Kattis or TSPLIB input, detected from the first token
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input).expect("error reading input");
+    let instance = tsplib::parse_instance(&input).expect("error parsing input");
+    let points = &instance.points;
+    let metric = options.metric.unwrap_or_else(|| {
+        instance.edge_weight_type.metric().expect("unsupported EDGE_WEIGHT_TYPE")
+    }).fit_to(points);
     // let result = greedy_tour(&points);
 
-    let graph = Graph::new(&points);
+    let warm_start = options.warm_start.as_ref().map(|path| {
+        let content = fs::read_to_string(path).expect("error reading warm start tour");
+        tsplib::parse_tour(&content, instance.dimension).expect("error parsing warm start tour")
+    });
 
-    // for i in 0..graph.num_nodes {
-    //     for j in 0..graph.num_nodes {
-    //         println!("Edge between ({:?}) has length {:?}", (i,j), graph.get_edge(i, j));
-    //     }
-    // }
+    let delaunay = LazyDelaunay::new(points, metric);
+    assert!(!matches!(options.candidates, Some(CandidateSet::Alpha { .. })) || instance.dimension <= MAX_DENSE_NODES, "alpha candidates need all distances, at most {} nodes", MAX_DENSE_NODES);
+    assert!(!options.lower_bound || instance.dimension <= MAX_DENSE_NODES, "--lower-bound needs all distances, at most {} nodes", MAX_DENSE_NODES);
+    assert!(!options.bottleneck || instance.dimension <= MAX_DENSE_NODES, "--bottleneck needs all distances, at most {} nodes", MAX_DENSE_NODES);
+    // the huge instances only run the local search from the Delaunay greedy tour
+    assert!(instance.dimension <= MAX_DENSE_NODES || (options.annealing.is_none() && options.matching.is_none() && options.shortcut.is_none()),
+        "--annealing, --matching and --shortcut need a distance matrix, at most {} nodes", MAX_DENSE_NODES);
+    let tour = if instance.dimension > MAX_DENSE_NODES {
+        // no distance matrix fits into memory: start from the Delaunay graph
+        // and compute the distances the local search asks for on demand;
+        // printing 50k lines takes a while, so stop a bit earlier
+        let graph = LazyDistances::new(points, metric);
+        let tour = warm_start.unwrap_or_else(|| delaunay.get().greedy_tour());
+        let candidates = delaunay.get().candidate_list(NUM_CANDIDATES);
+        let tour = or_opt(&graph, &candidates, two_opt(&graph, &candidates, tour, start_time, 1900), start_time, 1900);
+        iterated_local_search(&graph, &candidates, tour, SEED, start_time, 1900)
+    } else if instance.dimension > MAX_FLAT_NODES {
+        solve(&Graph::with_metric(points, metric), points, &delaunay, warm_start, &options, start_time)
+    } else {
+        solve(&FlatMatrix::new(points, metric), points, &delaunay, warm_start, &options, start_time)
+    };
 
+    if let Some(path) = &options.tour_out {
+        let content = tsplib::write_tour(instance.name.as_deref(), &tour, metric.tour_length(points, &tour));
+        fs::write(path, content).expect("error writing tour");
+    }
 
     // output
-    for res in christofidis(&graph, true) {
+    for res in tour {
         println!("{:?}", res);
     }
 }
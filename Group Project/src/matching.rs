@@ -0,0 +1,268 @@
+// Minimum weight perfect matchings on the odd degree nodes of Christofides.
+//
+// The exact blossom algorithm on the complete graph is O(m^3) and dominates
+// the running time at n = 1000. The cheaper strategies exploit that the
+// optimal matching almost only uses edges between near neighbors.
+
+use std::time::{Duration, Instant};
+use crate::blossom;
+use crate::distance::DistanceOracle;
+use crate::mwmatching::{min_weight_perfect_matching, IncrementalMatching, Vertex, SENTINEL};
+use crate::neighbors::CandidateList;
+use crate::tour::DontLookBits;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchingStrategy {
+    // exact on the complete graph
+    Exact,
+    // exact on the graph of the k nearest neighbors of every node; nodes it
+    // leaves unmatched get edges to all others and the matching continues
+    // from there
+    SparseExact { k: usize },
+    // greedy on the k nearest neighbor pairs, then 2-opt moves exchanging
+    // the partners of two matched edges
+    GreedyTwoOpt { k: usize },
+}
+
+pub struct MatchingResult {
+    pub pairs: Vec<(i32, i32)>,
+    pub weight: i64,
+    pub time: Duration,
+}
+
+// the distances between the given nodes, renumbered to 0..nodes.len()
+struct Subset<'a, D> {
+    graph: &'a D,
+    nodes: &'a [i32],
+}
+
+impl<D: DistanceOracle> DistanceOracle for Subset<'_, D> {
+    fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn get_edge(&self, x: i32, y: i32) -> i32 {
+        self.graph.get_edge(self.nodes[x as usize], self.nodes[y as usize])
+    }
+}
+
+// Perfect matching of an even number of nodes with the given strategy.
+pub fn perfect_matching<D: DistanceOracle>(graph: &D, nodes: &[i32], strategy: MatchingStrategy) -> MatchingResult {
+    let start_time = Instant::now();
+    let subset = Subset { graph, nodes };
+    let mates = match strategy {
+        MatchingStrategy::Exact => {
+            let mut mates = vec![SENTINEL; nodes.len()];
+            exact(&subset, &(0..nodes.len()).collect::<Vec<usize>>(), &mut mates);
+            mates
+        }
+        MatchingStrategy::SparseExact { k } => sparse_exact(&subset, k),
+        MatchingStrategy::GreedyTwoOpt { k } => greedy_two_opt(&subset, k),
+    };
+
+    let mut pairs = Vec::with_capacity(nodes.len() / 2);
+    let mut weight = 0;
+    for (i, &j) in mates.iter().enumerate() {
+        if i < j {
+            pairs.push((nodes[i], nodes[j]));
+            weight += subset.get_edge(i as i32, j as i32) as i64;
+        }
+    }
+    MatchingResult { pairs, weight, time: start_time.elapsed() }
+}
+
+// Matches the given nodes among each other on their complete graph, the
+// result goes into mates.
+fn exact<D: DistanceOracle>(graph: &D, nodes: &[usize], mates: &mut [usize]) {
+    let mut edges = Vec::new();
+    for i in 0..nodes.len() {
+        for j in i + 1..nodes.len() {
+            edges.push((i, j, graph.get_edge(nodes[i] as i32, nodes[j] as i32)));
+        }
+    }
+    let (pairs, _) = min_weight_perfect_matching(edges).expect("odd number of nodes to match");
+    for (i, j) in pairs {
+        mates[nodes[i]] = nodes[j];
+        mates[nodes[j]] = nodes[i];
+    }
+}
+
+// every pair of nodes where one is among the k nearest of the other, once
+fn neighbor_pairs<D: DistanceOracle>(graph: &D, k: usize) -> Vec<(usize, usize)> {
+    let candidates = CandidateList::new(graph, k);
+    let mut pairs: Vec<(usize, usize)> = (0..graph.num_nodes() as i32)
+        .flat_map(|x| candidates.get(x).iter().map(move |&y| (x.min(y) as usize, x.max(y) as usize)))
+        .collect();
+    pairs.sort_unstable();
+    pairs.dedup();
+    pairs
+}
+
+// how often sparse_exact doubles k when the neighbor graph has no perfect
+// matching, before leaving the rest to the fallback
+const GROW_STEPS: usize = 2;
+
+fn sparse_exact<D: DistanceOracle>(graph: &D, k: usize) -> Vec<usize> {
+    let m = graph.num_nodes();
+    // the neighbor graph need not have a perfect matching, e.g. two far
+    // apart clusters of odd size; the unweighted matching finds out much
+    // faster than the weighted one, and more neighbors often help
+    let mut k = k;
+    let mut pairs = neighbor_pairs(graph, k);
+    for _ in 0..GROW_STEPS {
+        if k + 1 >= m || blossom::Graph::from_edges(m, &pairs).full_matching().is_some() {
+            break;
+        }
+        k *= 2;
+        pairs = neighbor_pairs(graph, k);
+    }
+    // maximize max - wt like min_weight_perfect_matching; the edges added
+    // later may be longer than max, the maximum-cardinality matching does not
+    // mind negative weights
+    let maxweight = pairs.iter().map(|&(i, j)| graph.get_edge(i as i32, j as i32)).max().unwrap_or(0);
+    let edges = pairs.into_iter().map(|(i, j)| (i, j, maxweight - graph.get_edge(i as i32, j as i32))).collect();
+    let mut matching = IncrementalMatching::new(edges);
+
+    // Matching the single nodes among themselves is not optimal, starting
+    // from the neighbor graph optimum with complete edges for them is and
+    // takes only a few stages.
+    let unmatched: Vec<usize> = (0..m).filter(|&i| matching.mate()[i] == SENTINEL).collect();
+    if !unmatched.is_empty() {
+        let mut present = vec![true; m];
+        for &i in &unmatched {
+            matching.remove_vertex(i);
+            present[i] = false;
+        }
+        for &i in &unmatched {
+            let neighbors: Vec<(Vertex, i32)> = (0..m)
+                .filter(|&j| present[j])
+                .map(|j| (j, maxweight - graph.get_edge(i as i32, j as i32)))
+                .collect();
+            // removed numbers are reused smallest first
+            assert_eq!(matching.add_vertex(&neighbors), i);
+            present[i] = true;
+        }
+        matching.solve();
+    }
+    matching.mate().clone()
+}
+
+fn greedy_two_opt<D: DistanceOracle>(graph: &D, k: usize) -> Vec<usize> {
+    let m = graph.num_nodes();
+    let mut mates = vec![SENTINEL; m];
+    let mut pairs: Vec<(i32, usize, usize)> = neighbor_pairs(graph, k).into_iter()
+        .map(|(i, j)| (graph.get_edge(i as i32, j as i32), i, j))
+        .collect();
+    pairs.sort_unstable();
+    for (_, i, j) in pairs {
+        if mates[i] == SENTINEL && mates[j] == SENTINEL {
+            mates[i] = j;
+            mates[j] = i;
+        }
+    }
+    // the rest is matched to the nearest free node, there are only a few
+    let mut unmatched: Vec<usize> = (0..m).filter(|&i| mates[i] == SENTINEL).collect();
+    while let Some(i) = unmatched.pop() {
+        let (at, &j) = unmatched.iter().enumerate().min_by_key(|&(_, &j)| graph.get_edge(i as i32, j as i32)).unwrap();
+        unmatched.swap_remove(at);
+        mates[i] = j;
+        mates[j] = i;
+    }
+
+    // 2-opt: (a, b) and (c, d) become (a, c) and (b, d) for a candidate c of a
+    let candidates = CandidateList::new(graph, k);
+    let mut queue = DontLookBits::empty(m);
+    for a in 0..m as i32 {
+        queue.push(a);
+    }
+    while let Some(a) = queue.pop() {
+        let b = mates[a as usize] as i32;
+        let old_a = graph.get_edge(a, b);
+        for &c in candidates.get(a) {
+            let new_a = graph.get_edge(a, c);
+            if new_a >= old_a {
+                break;
+            }
+            let d = mates[c as usize] as i32;
+            if c == b {
+                continue;
+            }
+            let gain = old_a + graph.get_edge(c, d) - new_a - graph.get_edge(b, d);
+            if gain > 0 {
+                mates[a as usize] = c as usize;
+                mates[c as usize] = a as usize;
+                mates[b as usize] = d as usize;
+                mates[d as usize] = b as usize;
+                for x in [a, b, c, d] {
+                    queue.push(x);
+                }
+                break;
+            }
+        }
+    }
+    mates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use crate::utils::{Graph, SimpleRng};
+
+    fn assert_perfect(result: &MatchingResult, nodes: &[i32], graph: &Graph) {
+        let mut matched: Vec<i32> = result.pairs.iter().flat_map(|&(x, y)| [x, y]).collect();
+        matched.sort();
+        let mut expected = nodes.to_vec();
+        expected.sort();
+        assert_eq!(matched, expected);
+        let weight: i64 = result.pairs.iter().map(|&(x, y)| graph.get_edge(x, y) as i64).sum();
+        assert_eq!(result.weight, weight);
+    }
+
+    #[test]
+    fn strategies_are_perfect() {
+        let mut rng = SimpleRng::new(12);
+        let points: Vec<(f64, f64)> = (0..300).map(|_| (rng.next_f64() * 1000.0, rng.next_f64() * 1000.0)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let nodes: Vec<i32> = (0..300).filter(|x| x % 3 != 0).collect();
+        let exact = perfect_matching(&graph, &nodes, MatchingStrategy::Exact);
+        assert_perfect(&exact, &nodes, &graph);
+        for strategy in [MatchingStrategy::SparseExact { k: 8 }, MatchingStrategy::GreedyTwoOpt { k: 8 }] {
+            let result = perfect_matching(&graph, &nodes, strategy);
+            assert_perfect(&result, &nodes, &graph);
+            assert!(result.weight >= exact.weight);
+            // all of them are close to the optimum on uniform points
+            assert!(result.weight as f64 <= exact.weight as f64 * 1.25, "{:?}: {} vs {}", strategy, result.weight, exact.weight);
+        }
+    }
+
+    #[test]
+    fn sparse_falls_back_between_clusters() {
+        // two clusters of three, the nearest neighbors never leave a cluster
+        let points = vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (100.0, 0.0), (101.0, 0.0), (100.0, 1.0)];
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let nodes: Vec<i32> = (0..6).collect();
+        for strategy in [MatchingStrategy::SparseExact { k: 2 }, MatchingStrategy::GreedyTwoOpt { k: 2 }] {
+            let result = perfect_matching(&graph, &nodes, strategy);
+            assert_perfect(&result, &nodes, &graph);
+            // one pair has to cross, the optimum 101 uses (1, 3) of length 99
+            assert!(result.pairs.iter().any(|&(x, y)| (x < 3) != (y < 3)));
+            assert!(result.weight <= 102);
+        }
+    }
+
+    #[test]
+    fn sparse_fallback_is_optimal() {
+        // two clusters of 21, even 8 nearest neighbors stay in the cluster
+        let mut rng = SimpleRng::new(13);
+        let points: Vec<(f64, f64)> = (0..42)
+            .map(|i| (rng.next_f64() * 100.0 + if i < 21 { 0.0 } else { 1000.0 }, rng.next_f64() * 100.0))
+            .collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let nodes: Vec<i32> = (0..42).collect();
+        let exact = perfect_matching(&graph, &nodes, MatchingStrategy::Exact);
+        let result = perfect_matching(&graph, &nodes, MatchingStrategy::SparseExact { k: 2 });
+        assert_perfect(&result, &nodes, &graph);
+        assert_eq!(result.weight, exact.weight);
+    }
+}
@@ -0,0 +1,154 @@
+// Distance functions between two points.
+//
+// The TSPLIB metrics follow the definitions in the TSPLIB documentation
+// (Reinelt, 1995) and are integer valued. Euclidean is the exact f64
+// distance; the solvers work on integer lengths, so it is stored in fixed
+// point, as a multiple of 1 / scale.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Euc2d,
+    Ceil2d,
+    Att,
+    Geo,
+    Man2d,
+    Max2d,
+    Euclidean { scale: f64 },
+}
+
+// fit_to picks the Euclidean scale such that no edge is longer than this;
+// sums of a few dozen edges still fit into an i32
+const EUCLIDEAN_RANGE: f64 = (1 << 24) as f64;
+
+impl Metric {
+    // accepts the TSPLIB keywords plus EUCLIDEAN for the exact distance
+    pub fn from_name(name: &str) -> Option<Metric> {
+        match name.to_uppercase().as_str() {
+            "EUC_2D" => Some(Metric::Euc2d),
+            "CEIL_2D" => Some(Metric::Ceil2d),
+            "ATT" => Some(Metric::Att),
+            "GEO" => Some(Metric::Geo),
+            "MAN_2D" => Some(Metric::Man2d),
+            "MAX_2D" => Some(Metric::Max2d),
+            "EUCLIDEAN" => Some(Metric::Euclidean { scale: 1.0 }),
+            _ => None,
+        }
+    }
+
+    // The Euclidean scale as fine as the extent of the points allows; the
+    // other metrics stay as they are.
+    pub fn fit_to(self, points: &[(f64, f64)]) -> Metric {
+        match self {
+            Metric::Euclidean { .. } => {
+                let (mut low, mut high) = ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN));
+                for &(x, y) in points {
+                    low = (low.0.min(x), low.1.min(y));
+                    high = (high.0.max(x), high.1.max(y));
+                }
+                let diagonal = Metric::Euclidean { scale: 1.0 }.exact_distance(low, high);
+                Metric::Euclidean { scale: EUCLIDEAN_RANGE / diagonal.max(1.0) }
+            }
+            metric => metric,
+        }
+    }
+
+    // the integer length the solvers use
+    pub fn distance(&self, a: (f64, f64), b: (f64, f64)) -> i32 {
+        match self {
+            Metric::Euclidean { scale } => (self.exact_distance(a, b) * scale).round() as i32,
+            _ => self.exact_distance(a, b).round() as i32,
+        }
+    }
+
+    // for the TSPLIB metrics this is already the integer value
+    pub fn exact_distance(&self, (x1, y1): (f64, f64), (x2, y2): (f64, f64)) -> f64 {
+        let dx = x1 - x2;
+        let dy = y1 - y2;
+        match self {
+            Metric::Euc2d => (dx * dx + dy * dy).sqrt().round(),
+            Metric::Ceil2d => (dx * dx + dy * dy).sqrt().ceil(),
+            Metric::Att => {
+                // pseudo-Euclidean: rounded up whenever nint would round down
+                let r = ((dx * dx + dy * dy) / 10.0).sqrt();
+                let t = r.round();
+                if t < r { t + 1.0 } else { t }
+            }
+            Metric::Geo => geo_distance((x1, y1), (x2, y2)),
+            Metric::Man2d => (dx.abs() + dy.abs()).round(),
+            Metric::Max2d => dx.abs().round().max(dy.abs().round()),
+            Metric::Euclidean { .. } => (dx * dx + dy * dy).sqrt(),
+        }
+    }
+
+    // Euclidean up to rounding and scaling, so the Delaunay graph contains a
+    // minimum spanning tree and the nearest neighbors
+    pub fn is_euclidean(&self) -> bool {
+        matches!(self, Metric::Euc2d | Metric::Ceil2d | Metric::Att | Metric::Euclidean { .. })
+    }
+
+    // length of the closed tour, computed from the points; not scaled
+    pub fn tour_length(&self, points: &[(f64, f64)], tour: &[i32]) -> f64 {
+        (0..tour.len())
+            .map(|i| self.exact_distance(points[tour[i] as usize], points[tour[(i + 1) % tour.len()] as usize]))
+            .sum()
+    }
+}
+
+// coordinates are DDD.MM (degrees and minutes) of latitude and longitude
+fn geo_distance((x1, y1): (f64, f64), (x2, y2): (f64, f64)) -> f64 {
+    let (lat1, lon1) = (geo_radians(x1), geo_radians(y1));
+    let (lat2, lon2) = (geo_radians(x2), geo_radians(y2));
+    let q1 = (lon1 - lon2).cos();
+    let q2 = (lat1 - lat2).cos();
+    let q3 = (lat1 + lat2).cos();
+    (EARTH_RADIUS * (0.5 * ((1.0 + q1) * q2 - (1.0 - q1) * q3)).acos() + 1.0).trunc()
+}
+
+// TSPLIB uses these truncated constants, the reference tour lengths depend on them
+#[allow(clippy::approx_constant)]
+const GEO_PI: f64 = 3.141592;
+const EARTH_RADIUS: f64 = 6378.388;
+
+fn geo_radians(coordinate: f64) -> f64 {
+    let degrees = coordinate.trunc();
+    let minutes = coordinate - degrees;
+    GEO_PI * (degrees + 5.0 * minutes / 3.0) / 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounded_metrics() {
+        assert_eq!(Metric::Euc2d.distance((0.0, 0.0), (1.0, 1.0)), 1);
+        assert_eq!(Metric::Ceil2d.distance((0.0, 0.0), (1.0, 1.0)), 2);
+        assert_eq!(Metric::Man2d.distance((0.0, 0.0), (1.0, 1.0)), 2);
+        assert_eq!(Metric::Max2d.distance((0.0, 0.0), (1.4, -2.6)), 3);
+        assert_eq!(Metric::Euclidean { scale: 1.0 }.exact_distance((0.0, 0.0), (3.0, 4.0)), 5.0);
+    }
+
+    #[test]
+    fn euclidean_keeps_fractions() {
+        let points = [(0.0, 0.0), (1.0, 1.0), (1.0, 0.0), (1000.0, 1000.0)];
+        let metric = Metric::from_name("euclidean").unwrap().fit_to(&points);
+        // rounded these would both be 1
+        assert!(metric.distance(points[0], points[1]) > metric.distance(points[0], points[2]));
+        assert!(metric.distance(points[0], points[3]) as f64 <= EUCLIDEAN_RANGE);
+        assert_eq!(Metric::Euc2d.fit_to(&points), Metric::Euc2d);
+        assert!((metric.tour_length(&points, &[0, 2, 1]) - (2.0 + 2f64.sqrt())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn att_rounds_up() {
+        assert_eq!(Metric::Att.distance((0.0, 0.0), (30.0, 40.0)), 16);
+        assert_eq!(Metric::Att.distance((0.0, 0.0), (10.0, 0.0)), 4);
+    }
+
+    #[test]
+    fn geo_one_degree() {
+        // one degree of longitude on the equator
+        assert_eq!(Metric::Geo.distance((0.0, 0.0), (0.0, 1.0)), 112);
+        assert_eq!(Metric::Geo.distance((10.3, 20.45), (10.3, 20.45)), 1);
+    }
+}
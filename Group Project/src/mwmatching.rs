@@ -26,27 +26,112 @@
 
 /// This function takes time O(n ** 3)."""
 
-use std::cmp::max;
+use std::fmt::Debug;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+
+pub type Vertex         = usize;
+pub type Vertices       = Vec<Vertex>;
+pub type Weights<W>     = Vec<W>;
+pub type Edge<W = i32>  = (Vertex, Vertex, W); // i, j, wt
+pub type Edges<W = i32> = Vec<Edge<W>>;
+pub type Pairs          = Vec<(Vertex, Vertex)>; // i < j
+
+/// Edge weights of a matching. All dual variables are sums and differences
+/// of weights, and halves of those.
+pub trait Weight: Copy + Debug + Default + PartialOrd
+    + Add<Output = Self> + Sub<Output = Self> + Neg<Output = Self> + AddAssign + SubAssign {
+    const ZERO: Self;
+    /// Type the weight of a whole matching is summed up in.
+    type Total: Copy + Debug + PartialEq + Sum;
+
+    fn half(self) -> Self;
+
+    /// The smallest even value not below self, for integers; the duals of
+    /// all single vertices need the same parity for half to be exact.
+    fn raise_to_even(self) -> Self;
+
+    /// Slack up to this counts as zero; zero for integers, for floats it
+    /// absorbs the rounding errors of the dual updates. maxweight is the
+    /// largest absolute edge weight.
+    fn epsilon(maxweight: Self) -> Self;
+
+    fn widen(self) -> Self::Total;
+}
+
+macro_rules! integer_weight {
+    ($t:ty, $total:ty) => {
+        impl Weight for $t {
+            const ZERO: Self = 0;
+            type Total = $total;
+
+            /// Exact: the duals are doubled, so the slack between two S-vertices is even.
+            fn half(self) -> Self {
+                self / 2
+            }
+
+            fn raise_to_even(self) -> Self {
+                self + (self & 1)
+            }
+
+            fn epsilon(_maxweight: Self) -> Self {
+                0
+            }
+
+            fn widen(self) -> $total {
+                self as $total
+            }
+        }
+    };
+}
+
+integer_weight!(i32, i64);
+integer_weight!(i64, i64);
 
-pub type Vertex   = usize;
-pub type Vertices = Vec<Vertex>;
-pub type Weight   = i32;
-pub type Weights  = Vec<Weight>;
-pub type Edge     = (Vertex, Vertex, Weight); // i, j, wt
-pub type Edges    = Vec<Edge>;
+impl Weight for f64 {
+    const ZERO: Self = 0.0;
+    type Total = f64;
 
-pub const SENTINEL:Vertex    = <Vertex>::max_value();
+    fn half(self) -> Self {
+        self / 2.0
+    }
+
+    fn raise_to_even(self) -> Self {
+        self
+    }
+
+    fn epsilon(maxweight: Self) -> Self {
+        maxweight.max(1.0) * 1e-9
+    }
+
+    fn widen(self) -> f64 {
+        self
+    }
+}
+
+/// max for weights, which are only partially ordered
+fn max_weight<W: Weight>(a: W, b: W) -> W {
+    if a < b { b } else { a }
+}
+
+fn min_weight<W: Weight>(weights: &[W]) -> W {
+    weights.iter().copied().fold(weights[0], |a, b| if b < a { b } else { a })
+}
+
+pub const SENTINEL:Vertex    = Vertex::MAX;
 
 const CHECK_DELTA:bool   = false;
-const CHECK_OPTIMUM:bool = true;
+// Debug builds assert that verify finds no violations after every solve.
+const CHECK_OPTIMUM:bool = cfg!(debug_assertions);
 
 #[derive(Debug, Default)]
-pub struct Matching {
+pub struct Matching<W: Weight = i32> {
     nvertex:   usize,
     nedge:     usize,
-    maxweight: Weight,
-    edges:     Edges,
-    dualvar:          Weights,
+    maxweight: W,
+    epsilon:   W,
+    edges:     Edges<W>,
+    dualvar:          Weights<W>,
     endpoint:         Vertices,
     label:            Vertices,
     labelend:         Vertices,
@@ -63,10 +148,11 @@ pub struct Matching {
     neighbend:        Vec<Vertices>,
     allowedge:        Vec<bool>,
     maxcardinality:   bool,
+    warmstarted:      bool,
 }
 
-impl Matching {
-    pub fn new(edges: Edges) -> Matching {
+impl<W: Weight> Matching<W> {
+    pub fn new(edges: Edges<W>) -> Matching<W> {
         let mut matching = Matching::default();
         matching.edges = edges;
         if matching.edges.len() > 0 {
@@ -108,8 +194,11 @@ impl Matching {
             }
         }
 
-        //// Find the maximum edge weight.
-        self.maxweight = self.edges.iter().max_by_key(|x| x.2).unwrap().2;
+        //// Find the maximum edge weight, at least zero: with negative
+        //// duals the single vertices would look like they pay for matching.
+        self.maxweight = self.edges.iter().fold(W::ZERO, |m, e| max_weight(m, e.2));
+        let maxabs = self.edges.iter().fold(W::ZERO, |m, e| max_weight(m, max_weight(e.2, -e.2)));
+        self.epsilon = W::epsilon(maxabs);
 
         // If p is an edge endpoint,
         // endpoint[p] is the vertex to which endpoint p is attached.
@@ -204,7 +293,7 @@ impl Matching {
         // dualvar[b] = z(b) where z(b) is b's variable in the dual optimization
         // problem.
         self.dualvar = vec![self.maxweight;self.nvertex];
-        self.dualvar.extend(vec![0; self.nvertex]);
+        self.dualvar.extend(vec![W::ZERO; self.nvertex]);
 
         // If allowedge[k] is true, edge k has zero slack in the optimization
         // problem; if allowedge[k] is false, the edge's slack may or may not
@@ -217,9 +306,9 @@ impl Matching {
 
     // Return 2 * slack of edge k (does not work inside blossoms).
     #[inline]
-    fn slack(&self, k:Vertex) -> Weight {
+    fn slack(&self, k:Vertex) -> W {
         let (i, j, wt) = self.edges[k];
-        self.dualvar[i] + self.dualvar[j] - 2 * wt
+        self.dualvar[i] + self.dualvar[j] - (wt + wt)
     }
 
     /// Generate the leaf vertices of a blossom.
@@ -376,7 +465,7 @@ impl Matching {
         self.labelend[b] = self.labelend[bb];
 
         // Set dual variable to zero.
-        self.dualvar[b] = 0;
+        self.dualvar[b] = W::ZERO;
 
         // Relabel vertices.
         for v in self.blossom_leaves(b) {
@@ -441,7 +530,7 @@ impl Matching {
             self.blossomparent[s] = SENTINEL;
             if s < self.nvertex {
                 self.inblossom[s] = s;
-            } else if endstage && (self.dualvar[s] == 0) {
+            } else if endstage && (self.dualvar[s] == W::ZERO) {
                 // Recursively expand this sub-blossom.
                 self.expand_blossom(s, endstage);
             } else {
@@ -671,24 +760,44 @@ impl Matching {
         }
     }
 
-    /// Verify that the optimum solution has been reached.
-    fn verify_optimum(&self) {
-        let vdualoffset:Weight;
+    /// Offset added to all vertex duals to make them non-negative.
+    fn vertex_dual_offset(&self) -> W {
         if self.maxcardinality {
             // Vertices may have negative dual;
             // find a constant non-negative number to add to all vertex duals.
-            vdualoffset = max(0, -(self.dualvar[0..self.nvertex].iter().min().unwrap()));
+            max_weight(W::ZERO, -min_weight(&self.dualvar[0..self.nvertex]))
         } else {
-            vdualoffset = 0;
+            W::ZERO
+        }
+    }
+
+    /// Check the complementary slackness conditions of the current matching
+    /// and duals; they hold if and only if the matching is optimal.
+    /// Can be called before and after solve.
+    pub fn verify(&self) -> Report<W> {
+        let mut violations = vec![];
+        if self.edges.is_empty() {
+            return Report { violations };
         }
+        let vdualoffset = self.vertex_dual_offset();
+        // float slacks are only zero up to rounding
+        let eps = self.epsilon;
         // 0. all dual variables are non-negative
-        //assert min(dualvar[:nvertex]) + vdualoffset >= 0
-        //assert min(dualvar[nvertex:]) >= 0
+        for v in 0..self.nvertex {
+            if self.dualvar[v] + vdualoffset < -eps {
+                violations.push(Violation::NegativeDual { blossom: v, dual: self.dualvar[v] + vdualoffset });
+            }
+        }
+        for b in self.nvertex..2*self.nvertex {
+            if self.dualvar[b] < -eps {
+                violations.push(Violation::NegativeDual { blossom: b, dual: self.dualvar[b] });
+            }
+        }
         // 0. all edges have non-negative slack and
         // 1. all matched edges have zero slack;
         for k in 0..self.nedge {
             let (i, j, wt) = self.edges[k];
-            let mut s:i32  = self.dualvar[i] + self.dualvar[j] - 2*wt;
+            let mut s:W  = self.dualvar[i] + self.dualvar[j] - (wt + wt);
             let mut iblossoms = vec![i];
             let mut jblossoms = vec![j];
 
@@ -706,39 +815,68 @@ impl Matching {
                 if bi != bj {
                     break;
                 }
-                s += 2 * self.dualvar[bi];
+                s += self.dualvar[bi] + self.dualvar[bi];
+            }
+            if s < -eps {
+                violations.push(Violation::NegativeSlack { edge: k, slack: s });
             }
-            assert!(s >= 0);
             if (self.mate[i] / 2 == k) || (self.mate[j] / 2 == k) {
-                assert!(self.mate[i] / 2 == k);
-                assert!(self.mate[j] / 2 == k);
-                assert!(s == 0);
+                if (self.mate[i] / 2 != k) || (self.mate[j] / 2 != k) {
+                    violations.push(Violation::HalfMatched { edge: k });
+                }
+                if s > eps {
+                    violations.push(Violation::MatchedSlack { edge: k, slack: s });
+                }
             }
         }
         // 2. all single vertices have zero dual value;
         for v in 0..self.nvertex {
-            assert!((self.mate[v] != SENTINEL) || (self.dualvar[v] + vdualoffset == 0));
+            if (self.mate[v] == SENTINEL) && (self.dualvar[v] + vdualoffset > eps) {
+                violations.push(Violation::SingleDual { vertex: v, dual: self.dualvar[v] + vdualoffset });
+            }
         }
         // 3. all blossoms with positive dual value are full.
         for b in self.nvertex..2*self.nvertex {
-            if (self.blossombase[b] != SENTINEL) || (self.dualvar[b] > 0) {
-                assert!(self.blossomendps[b].len() % 2 == 1);
-                for (ix, &p) in self.blossomendps[b].iter().enumerate() {
-                    if (ix % 2) == 1 {
-                        assert!(self.mate[self.endpoint[p]] == p ^ 1);
-                        assert!(self.mate[self.endpoint[p ^ 1]] == p);
-                    }
+            if (self.blossombase[b] != SENTINEL) || (self.dualvar[b] > W::ZERO) {
+                let full = self.blossomendps[b].len() % 2 == 1
+                    && self.blossomendps[b].iter().enumerate().all(|(ix, &p)| (ix % 2) == 0
+                        || (self.mate[self.endpoint[p]] == p ^ 1 && self.mate[self.endpoint[p ^ 1]] == p));
+                if !full {
+                    violations.push(Violation::BlossomNotFull { blossom: b });
                 }
             }
         }
-        // Optimum verified
+        Report { violations }
+    }
+
+    /// The current dual solution: after solve, together with the matching
+    /// a certificate of its optimality.
+    pub fn duals(&self) -> Duals<W> {
+        let mut blossoms = vec![];
+        for b in self.nvertex..2*self.nvertex {
+            if self.blossombase[b] != SENTINEL {
+                blossoms.push(Blossom {
+                    id:       b,
+                    parent:   self.blossomparent[b],
+                    base:     self.blossombase[b],
+                    childs:   self.blossomchilds[b].clone(),
+                    vertices: self.blossom_leaves(b),
+                    dual:     self.dualvar[b],
+                });
+            }
+        }
+        Duals {
+            vertex:        self.dualvar[..self.nvertex].to_vec(),
+            vertex_offset: self.vertex_dual_offset(),
+            blossoms,
+        }
     }
 
     /// Check optimized delta2 against a trivial computation.
     fn check_delta2(&self) {
         for v in 0..self.nvertex {
             if self.label[self.inblossom[v]] == 0 {
-                let mut bd = 0;
+                let mut bd = W::ZERO;
                 let mut bk = SENTINEL;
                 for &p in &self.neighbend[v] {
                     let mut k = p / 2;
@@ -762,9 +900,9 @@ impl Matching {
     /// Check optimized delta3 against a trivial computation.
     fn check_delta3(&self) {
         let mut bk = SENTINEL;
-        let mut bd = 0;
+        let mut bd = W::ZERO;
         let mut tbk = SENTINEL;
-        let mut tbd = 0;
+        let mut tbd = W::ZERO;
 
         for b in 0..2*self.nvertex {
             if (self.blossomparent[b] == SENTINEL) && (self.label[b] == 1) {
@@ -802,7 +940,7 @@ impl Matching {
         }
 
         // Main loop: continue until no further improvement is possible.
-        let mut kslack = 0i32;
+        let mut kslack = W::ZERO;
         for _t in 0..self.nvertex {
 
             // Each iteration of this loop is a "stage".
@@ -858,7 +996,7 @@ impl Matching {
                         }
                         if !self.allowedge[k] {
                             kslack = self.slack(k);
-                            if kslack <= 0 {
+                            if kslack <= self.epsilon {
                                 // edge k has zero slack => it is allowable
                                 self.allowedge[k] = true
                             }
@@ -920,7 +1058,7 @@ impl Matching {
                 // (Note that our vertex dual variables, edge slacks and delta's
                 // are pre-multiplied by two.)
                 let mut deltatype = -1;
-                let mut delta = 0;
+                let mut delta = W::ZERO;
                 let mut deltaedge = 0;
                 let mut deltablossom = 0;
 
@@ -931,7 +1069,7 @@ impl Matching {
                 }
                 if !self.maxcardinality {
                     deltatype = 1;
-                    delta = *self.dualvar[0..self.nvertex].iter().min().unwrap();
+                    delta = min_weight(&self.dualvar[0..self.nvertex]);
                 }
                 // Compute delta2: the minimum slack on any edge between
                 // an S-vertex and a free vertex.
@@ -953,7 +1091,7 @@ impl Matching {
                         && (self.label[b] == 1)
                         && (self.bestedge[b] != SENTINEL) {
                         let kslack = self.slack(self.bestedge[b]);
-                        let d = kslack / 2;
+                        let d = kslack.half();
                         if (deltatype == -1) || (d < delta) {
                             delta = d;
                             deltatype = 3;
@@ -980,7 +1118,7 @@ impl Matching {
                     // verifyable.
                     assert!(self.maxcardinality);
                     deltatype = 1;
-                    delta = max(0, *(self.dualvar[..self.nvertex]).iter().min().unwrap());
+                    delta = max_weight(W::ZERO, min_weight(&self.dualvar[..self.nvertex]));
                 }
 
                 // Update dual variables according to delta.
@@ -1040,32 +1178,304 @@ impl Matching {
                 if  self.blossomparent[b] == SENTINEL
                     &&  self.blossombase[b] != SENTINEL
                     &&  self.label[b] == 1
-                    &&  self.dualvar[b] == 0 {
+                    &&  self.dualvar[b] == W::ZERO {
                     self.expand_blossom(b, true);
                 }
             }
         }
         // Verify that we reached the optimum solution.
-        if CHECK_OPTIMUM {
-            self.verify_optimum();
-        }
-
-        // Transform mate[] such that mate[v] is the vertex to which v is paired.
-        for v in 0..self.nvertex {
-            if self.mate[v] != SENTINEL {
-                self.mate[v] = self.endpoint[self.mate[v]];
+        // a warm start is only certain to reach the optimum if the result is perfect
+        let perfect = self.mate.iter().all(|&p| p != SENTINEL);
+        if CHECK_OPTIMUM && (!self.warmstarted || perfect) {
+            let report = self.verify();
+            debug_assert!(report.is_optimal(), "matching not optimal: {:?}", report.violations);
+            // the dual objective is twice the weight, exactly for integers
+            if self.epsilon == W::ZERO && (perfect || !self.maxcardinality) {
+                let twice: W::Total = (0..self.nvertex).filter(|&v| self.mate[v] != SENTINEL).map(|v| self.edges[self.mate[v] / 2].2.widen()).sum();
+                debug_assert_eq!(self.duals().objective(), twice);
             }
         }
+
+        // Transform mate[] such that mate[v] is the vertex to which v is paired;
+        // the endpoints stay for verify.
+        let mate: Vertices = self.mate.iter().map(|&p| if p != SENTINEL { self.endpoint[p] } else { SENTINEL }).collect();
         for v in 0..self.nvertex {
-            assert!(self.mate[v] == SENTINEL || self.mate[self.mate[v]] == v);
+            assert!(mate[v] == SENTINEL || mate[mate[v]] == v);
         }
 
-        self.mate.clone()
+        mate
     }
     pub fn max_cardinality(&mut self) -> &mut Self {
         self.maxcardinality = true;
         self
     }
+
+    /// Start from the given matching instead of the empty one: mate[v] is
+    /// the vertex v is paired with or SENTINEL, as returned by solve, and
+    /// dualvar[v] = 2 * u(v) as in Duals. Vertices missing from either list
+    /// are single or start with dual zero. Duals too low for an edge are
+    /// raised, at a single end if there is one, and pairs that are no edge
+    /// with zero slack are split, so any input is valid. Every stage of
+    /// solve matches two more vertices, so starting close to the optimum
+    /// takes only a few stages.
+    ///
+    /// Implies max_cardinality: without it all single vertices would need
+    /// the same and smallest dual. The result is certain to be optimal only
+    /// if it is perfect; verify tells.
+    pub fn warm_start(&mut self, mate: &[Vertex], dualvar: &[W]) -> &mut Self {
+        self.maxcardinality = true;
+        self.warmstarted = true;
+        if self.edges.is_empty() {
+            return self;
+        }
+        for v in 0..self.nvertex {
+            self.dualvar[v] = dualvar.get(v).copied().unwrap_or(W::ZERO);
+        }
+        let partner = |v: Vertex| mate.get(v).copied().unwrap_or(SENTINEL);
+
+        // Raising a dual only adds slack to the other edges.
+        for k in 0..self.nedge {
+            let s = self.slack(k);
+            if s < W::ZERO {
+                let (i, j, _wt) = self.edges[k];
+                let v = if partner(i) != SENTINEL && partner(j) == SENTINEL { j } else { i };
+                self.dualvar[v] -= s;
+            }
+        }
+        for v in 0..self.nvertex {
+            let w = partner(v);
+            if w == SENTINEL || w < v || w >= self.nvertex || partner(w) != v {
+                continue;
+            }
+            if let Some(&p) = self.neighbend[v].iter().find(|&&p| self.endpoint[p] == w) {
+                if self.slack(p / 2) <= self.epsilon {
+                    self.mate[v] = p;
+                    self.mate[w] = p ^ 1;
+                }
+            }
+        }
+        for v in 0..self.nvertex {
+            if self.mate[v] == SENTINEL {
+                self.dualvar[v] = self.dualvar[v].raise_to_even();
+            }
+        }
+        self
+    }
+
+    /// The duals in the form warm_start takes, see Duals::flat.
+    pub fn flat_duals(&self) -> Weights<W> {
+        self.duals().flat()
+    }
+}
+
+/// Maximum-weight maximum-cardinality matching of a graph whose vertices
+/// come and go, solved again from the previous matching and duals after
+/// every change. Meant for graphs that change little between solves, like
+/// the neighbor graph of the odd vertices of Christofides once the few
+/// vertices it leaves single get edges to all others.
+pub struct IncrementalMatching<W: Weight = i32> {
+    edges:   Edges<W>,
+    present: Vec<bool>,
+    mate:    Vertices,
+    dualvar: Weights<W>,
+}
+
+impl<W: Weight> IncrementalMatching<W> {
+    /// The vertices are 0 .. the largest one in "edges", matched right away.
+    pub fn new(edges: Edges<W>) -> IncrementalMatching<W> {
+        let nvertex = edges.iter().map(|&(i, j, _wt)| i.max(j) + 1).max().unwrap_or(0);
+        let mut matching = IncrementalMatching { edges, present: vec![true; nvertex], mate: vec![], dualvar: vec![] };
+        matching.solve();
+        matching
+    }
+
+    /// mate[v] is the vertex v is paired with, or SENTINEL.
+    pub fn mate(&self) -> &Vertices {
+        &self.mate
+    }
+
+    /// Add a vertex with edges to the given vertices, reusing the number of
+    /// a removed one if possible. It stays single until the next solve.
+    pub fn add_vertex(&mut self, neighbors: &[(Vertex, W)]) -> Vertex {
+        let v = match self.present.iter().position(|&p| !p) {
+            Some(v) => v,
+            None => {
+                self.present.push(false);
+                self.mate.push(SENTINEL);
+                self.dualvar.push(W::ZERO);
+                self.present.len() - 1
+            }
+        };
+        self.present[v] = true;
+        // warm_start raises it just enough for the new edges
+        self.dualvar[v] = W::ZERO;
+        for &(u, wt) in neighbors {
+            assert!(u != v && self.present[u]);
+            self.edges.push((u.min(v), u.max(v), wt));
+        }
+        v
+    }
+
+    /// Remove a vertex and its edges; its partner becomes single.
+    pub fn remove_vertex(&mut self, v: Vertex) {
+        self.present[v] = false;
+        self.edges.retain(|&(i, j, _wt)| i != v && j != v);
+        let w = self.mate[v];
+        if w != SENTINEL {
+            self.mate[w] = SENTINEL;
+            self.mate[v] = SENTINEL;
+        }
+    }
+
+    /// Match the vertices again, starting from the previous result.
+    pub fn solve(&mut self) -> &Vertices {
+        // number the present vertices consecutively, removed ones would
+        // stay single and spoil the check for a perfect matching
+        let n = self.present.len();
+        let ids: Vertices = (0..n).filter(|&v| self.present[v]).collect();
+        let mut index = vec![SENTINEL; n];
+        for (i, &v) in ids.iter().enumerate() {
+            index[v] = i;
+        }
+        let edges = self.edges.iter().map(|&(i, j, wt)| (index[i], index[j], wt)).collect();
+        let mate: Vertices = ids.iter().map(|&v| self.mate.get(v).map_or(SENTINEL, |&w| if w == SENTINEL { w } else { index[w] })).collect();
+        let dualvar: Weights<W> = ids.iter().map(|&v| self.dualvar.get(v).copied().unwrap_or(W::ZERO)).collect();
+
+        let mut matching = Matching::new(edges);
+        let solved = matching.warm_start(&mate, &dualvar).solve();
+        let flat = if solved.is_empty() { vec![] } else { matching.flat_duals() };
+        self.mate = vec![SENTINEL; n];
+        self.dualvar = vec![W::ZERO; n];
+        for (i, &v) in ids.iter().enumerate() {
+            if let Some(&w) = solved.get(i) {
+                self.mate[v] = if w == SENTINEL { w } else { ids[w] };
+                self.dualvar[v] = flat[i];
+            }
+        }
+        &self.mate
+    }
+}
+
+/// A non-trivial blossom of the dual solution. The blossoms form a laminar
+/// family: two of them are either disjoint or one contains the other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blossom<W> {
+    /// blossom number, nvertex .. 2*nvertex
+    pub id:       Vertex,
+    /// enclosing blossom, or SENTINEL for a top-level blossom
+    pub parent:   Vertex,
+    pub base:     Vertex,
+    /// sub-blossoms starting with the base and going round the blossom;
+    /// numbers below nvertex are vertices
+    pub childs:   Vertices,
+    /// the vertices in the blossom, an odd number
+    pub vertices: Vertices,
+    /// z(b)
+    pub dual:     W,
+}
+
+/// Dual variables of the matching LP, with the scaling of the algorithm:
+/// vertex[v] is 2 * u(v) and a blossom dual is z(b), so the slack of edge
+/// (i, j) is vertex[i] + vertex[j] - 2 * wt + 2 * (z of the blossoms
+/// containing both).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duals<W> {
+    pub vertex:        Weights<W>,
+    /// With max_cardinality the vertex duals may be negative; adding this
+    /// to all of them makes them non-negative. Zero otherwise.
+    pub vertex_offset: W,
+    pub blossoms:      Vec<Blossom<W>>,
+}
+
+impl<W: Weight> Duals<W> {
+    /// Twice the dual objective, sum of u(v) plus z(b) * (|b| - 1) / 2 over
+    /// the blossoms, without the offset. Without max_cardinality it is an
+    /// upper bound on the weight of every matching, and at the optimum equal
+    /// to the weight of the matching found; with max_cardinality the same
+    /// holds for perfect matchings.
+    pub fn objective(&self) -> W::Total {
+        let vertices = self.vertex.iter().map(|u| u.widen());
+        let blossoms = self.blossoms.iter().flat_map(|b| std::iter::repeat_n(b.dual.widen(), b.vertices.len() - 1));
+        vertices.chain(blossoms).sum()
+    }
+
+    /// Vertex duals with the dual of every blossom added to its vertices:
+    /// the edges inside keep their slack, only the matched edge leaving a
+    /// blossom with positive dual loses its zero slack.
+    pub fn flat(&self) -> Weights<W> {
+        let mut flat = self.vertex.clone();
+        for b in &self.blossoms {
+            for &v in &b.vertices {
+                flat[v] += b.dual;
+            }
+        }
+        flat
+    }
+}
+
+/// A violated complementary slackness condition; slacks are doubled like
+/// the vertex duals.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation<W> {
+    /// a vertex or blossom dual is negative (vertex ones after the offset)
+    NegativeDual   { blossom: Vertex, dual: W },
+    /// an edge has negative slack, the duals are not feasible
+    NegativeSlack  { edge: Vertex, slack: W },
+    /// a matched edge has positive slack
+    MatchedSlack   { edge: Vertex, slack: W },
+    /// only one end of an edge has it as its matched edge
+    HalfMatched    { edge: Vertex },
+    /// a single vertex has positive dual
+    SingleDual     { vertex: Vertex, dual: W },
+    /// a blossom with positive dual is not full
+    BlossomNotFull { blossom: Vertex },
+}
+
+/// Result of Matching::verify.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report<W> {
+    pub violations: Vec<Violation<W>>,
+}
+
+impl<W> Report<W> {
+    pub fn is_optimal(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// The largest matching found when the graph has no perfect matching.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotPerfect {
+    pub pairs:     Pairs,
+    pub unmatched: Vertices,
+}
+
+/// Compute a perfect matching of minimum total weight in the graph given by
+/// "edges", with the same conventions as Matching::new. The vertices are
+/// 0 .. the largest one in "edges", isolated ones included.
+///
+/// Return the matched pairs (i, j) with i < j and the total weight, or the
+/// minimum weight maximum-cardinality matching if no perfect one exists.
+pub fn min_weight_perfect_matching<W: Weight>(edges: Edges<W>) -> Result<(Pairs, W::Total), NotPerfect> {
+    if edges.is_empty() {
+        return Ok((vec![], std::iter::empty().sum()));
+    }
+    // Maximize max - wt: among the maximum-cardinality matchings, which all
+    // have the same number of edges, this minimizes the weight. Shifting by
+    // the maximum keeps all weights non-negative, as the duals start at the
+    // maximum weight.
+    let maxweight = edges.iter().fold(edges[0].2, |m, e| max_weight(m, e.2));
+    let inverted = edges.iter().map(|&(i, j, wt)| (i, j, maxweight - wt)).collect();
+    let mate = Matching::new(inverted).max_cardinality().solve();
+
+    let pairs: Vec<(Vertex, Vertex)> = (0..mate.len()).filter(|&v| mate[v] != SENTINEL && v < mate[v]).map(|v| (v, mate[v])).collect();
+    let unmatched: Vertices = (0..mate.len()).filter(|&v| mate[v] == SENTINEL).collect();
+    if !unmatched.is_empty() {
+        return Err(NotPerfect { pairs, unmatched });
+    }
+    // there is at most one edge between two vertices
+    let weight = edges.iter().filter(|&&(i, j, _)| mate[i] == j).map(|e| e.2.widen()).sum();
+    Ok((pairs, weight))
 }
 
 /// shifts back of vec to front
@@ -1088,109 +1498,340 @@ fn pos_neg_index(v: &Vertices, index: i32) -> Vertex {
 #[cfg(test)]
 mod tests {
     use super::*;
-    #[test]
-    fn no_edges() {
-        assert!(Matching::new(vec![]).solve() == vec![]);
-    }
+    use crate::utils::SimpleRng;
 
-    #[test]
-    fn single_edge() {
-        assert!(Matching::new(vec![(0,1,1)]).solve() == vec![1, 0]);
-    }
+    // the regression tests, for every weight type
+    macro_rules! weight_tests {
+        ($name:ident, $t:ty) => {
+            mod $name {
+                use super::super::*;
 
-    #[test]
-    fn multiple_edges() {
-        assert!(Matching::new(vec![(1,2,10), (2,3,11)]).solve() == vec![SENTINEL, SENTINEL, 3, 2]);
-    }
+                fn m(edges: Vec<(Vertex, Vertex, i32)>) -> Matching<$t> {
+                    Matching::new(edges.into_iter().map(|(i, j, wt)| (i, j, wt as $t)).collect())
+                }
 
-    #[test]
-    fn not_max_cardinality() {
-        assert!(Matching::new(vec![(1,2,5), (2,3,11), (3,4,5)]).solve() == vec![SENTINEL, SENTINEL, 3, 2, SENTINEL]);
-    }
+                #[test]
+                fn no_edges() {
+                    assert!(m(vec![]).solve() == vec![]);
+                }
 
-    #[test]
-    fn test_max_cardinality() {
-        assert!(Matching::new(vec![(1,2,5), (2,3,11), (3,4,5)]).max_cardinality().solve() == vec![SENTINEL, 2, 1, 4, 3]);
-    }
+                #[test]
+                fn single_edge() {
+                    assert!(m(vec![(0,1,1)]).solve() == vec![1, 0]);
+                }
 
-    #[test]
-    fn negative_weights() {
-        assert!(Matching::new(vec![(1,2,2), (1,3,-2), (2,3,1), (2,4,-1), (3,4,-6) ]).solve() == vec![SENTINEL, 2, 1, SENTINEL, SENTINEL]);
-        assert!(Matching::new(vec![(1,2,2), (1,3,-2), (2,3,1), (2,4,-1), (3,4,-6) ]).max_cardinality().solve() == vec![SENTINEL, 3, 4, 1, 2]);
+                #[test]
+                fn multiple_edges() {
+                    assert!(m(vec![(1,2,10), (2,3,11)]).solve() == vec![SENTINEL, SENTINEL, 3, 2]);
+                }
+
+                #[test]
+                fn not_max_cardinality() {
+                    assert!(m(vec![(1,2,5), (2,3,11), (3,4,5)]).solve() == vec![SENTINEL, SENTINEL, 3, 2, SENTINEL]);
+                }
+
+                #[test]
+                fn test_max_cardinality() {
+                    assert!(m(vec![(1,2,5), (2,3,11), (3,4,5)]).max_cardinality().solve() == vec![SENTINEL, 2, 1, 4, 3]);
+                }
+
+                #[test]
+                fn negative_weights() {
+                    assert!(m(vec![(1,2,2), (1,3,-2), (2,3,1), (2,4,-1), (3,4,-6) ]).solve() == vec![SENTINEL, 2, 1, SENTINEL, SENTINEL]);
+                    assert!(m(vec![(1,2,2), (1,3,-2), (2,3,1), (2,4,-1), (3,4,-6) ]).max_cardinality().solve() == vec![SENTINEL, 3, 4, 1, 2]);
+                }
+
+                #[test]
+                /// create S-blossom and use it for augmentation
+                fn s_augmentation() {
+                    assert!(m(vec![(1,2,8), (1,3,9), (2,3,10), (3,4,7)]).solve() == vec![SENTINEL, 2, 1, 4, 3]);
+                    assert!(m(vec![(1,2,8), (1,3,9), (2,3,10), (3,4,7), (1,6,5), (4,5,6)]).solve() == vec![SENTINEL, 6, 3, 2, 5, 4, 1]);
+                }
+
+                #[test]
+                /// create S-blossom, relabel as T-blossom, use for augmentation
+                fn s_t_relabel_augmentation() {
+                    assert!(m(vec![(1,2,9), (1,3,8), (2,3,10), (1,4,5), (4,5,4), (1,6,3)]).solve() == vec![SENTINEL, 6, 3, 2, 5, 4, 1]);
+                    assert!(m(vec![(1,2,9), (1,3,8), (2,3,10), (1,4,5), (4,5,3), (1,6,4)]).solve() == vec![SENTINEL, 6, 3, 2, 5, 4, 1]);
+                    assert!(m(vec![(1,2,9), (1,3,8), (2,3,10), (1,4,5), (4,5,3), (3,6,4)]).solve() == vec![SENTINEL, 2, 1, 6, 5, 4, 3]);
+                }
+
+                #[test]
+                /// create nested S-blossom, use for augmentation
+                fn s_nested_augmentation() {
+                    assert!(m(vec![(1,2,9), (1,3,9), (2,3,10), (2,4,8), (3,5,8), (4,5,10), (5,6,6)]).solve() == vec![SENTINEL, 3, 4, 1, 2, 6, 5]);
+                }
+
+                #[test]
+                /// create S-blossom, relabel as S, include in nested S-blossom
+                fn s_relabel_nested() {
+                    assert!(m(vec![(1,2,10), (1,7,10), (2,3,12), (3,4,20), (3,5,20), (4,5,25), (5,6,10), (6,7,10), (7,8,8)]).solve() == vec![SENTINEL, 2, 1, 4, 3, 6, 5, 8, 7]);
+                }
+
+                #[test]
+                /// create nested S-blossom, augment, expand recursively
+                fn s_nested_expand_recursively() {
+                    assert!(m(vec![(1,2,8), (1,3,8), (2,3,10), (2,4,12), (3,5,12), (4,5,14), (4,6,12), (5,7,12), (6,7,14), (7,8,12)]).solve() == vec![SENTINEL, 2, 1, 5, 6, 3, 4, 8, 7]);
+                }
+
+                #[test]
+                /// create S-blossom, relabel as T, expand
+                fn s_t_expand() {
+                    assert!(m(vec![(1,2,23), (1,5,22), (1,6,15), (2,3,25), (3,4,22), (4,5,25), (4,8,14), (5,7,13)]).solve() == vec![SENTINEL, 6, 3, 2, 8, 7, 1, 5, 4 ]);
+                }
+
+                #[test]
+                /// create nested S-blossom, relabel as T, expand
+                fn s_nest_t_expand() {
+                    assert!(m(vec![(1,2,19), (1,3,20), (1,8,8), (2,3,25), (2,4,18), (3,5,18), (4,5,13), (4,7,7), (5,6,7)]).solve() == vec![SENTINEL, 8, 3, 2, 7, 6, 5, 4, 1 ]);
+                }
+
+                #[test]
+                /// create blossom, relabel as T in more than one way, expand, augment
+                fn tnasty_expand() {
+                    assert!(m(vec![(1,2,45), (1,5,45), (2,3,50), (3,4,45), (4,5,50), (1,6,30), (3,9,35), (4,8,35), (5,7,26), (9,10,5)]).solve() == vec![SENTINEL, 6, 3, 2, 8, 7, 1, 5, 4, 10, 9 ]);
+                }
+
+                #[test]
+                /// again but slightly different
+                fn tnasty2_expand() {
+                    assert!(m(vec![(1,2,45), (1,5,45), (2,3,50), (3,4,45), (4,5,50), (1,6,30), (3,9,35), (4,8,26), (5,7,40), (9,10,5)]).solve() == vec![SENTINEL, 6, 3, 2, 8, 7, 1, 5, 4, 10, 9 ]);
+                }
+
+                #[test]
+                /// create blossom, relabel as T, expand such that a new least-slack S-to-free edge is produced, augment
+                fn t_expand_leastslack() {
+                    assert!(m(vec![(1,2,45), (1,5,45), (2,3,50), (3,4,45), (4,5,50), (1,6,30), (3,9,35), (4,8,28), (5,7,26), (9,10,5)]).solve() == vec![SENTINEL, 6, 3, 2, 8, 7, 1, 5, 4, 10, 9 ]);
+                }
+
+                #[test]
+                /// create nested blossom, relabel as T in more than one way, expand outer blossom such that inner blossom ends up on an augmenting path
+                fn nest_tnasty_expand() {
+                    assert!(m(vec![(1,2,45), (1,7,45), (2,3,50), (3,4,45), (4,5,95), (4,6,94), (5,6,94), (6,7,50), (1,8,30), (3,11,35), (5,9,36), (7,10,26), (11,12,5)]).solve() == vec![SENTINEL, 8, 3, 2, 6, 9, 4, 10, 1, 5, 7, 12, 11]);
+                }
+
+                #[test]
+                /// create nested S-blossom, relabel as S, expand recursively
+                fn nest_relabel_expand() {
+                    assert!(m(vec![(1,2,40), (1,3,40), (2,3,60), (2,4,55), (3,5,55), (4,5,50), (1,8,15), (5,7,30), (7,6,10), (8,10,10), (4,9,30)]).solve() == vec![SENTINEL, 2, 1, 5, 9, 3, 7, 6, 10, 4, 8 ]);
+                }
+            }
+        };
     }
 
+    weight_tests!(weight_i32, i32);
+    weight_tests!(weight_i64, i64);
+    weight_tests!(weight_f64, f64);
+
     #[test]
-    /// create S-blossom and use it for augmentation
-    fn s_augmentation() {
-        assert!(Matching::new(vec![(1,2,8), (1,3,9), (2,3,10), (3,4,7)]).solve() == vec![SENTINEL, 2, 1, 4, 3]);
-        assert!(Matching::new(vec![(1,2,8), (1,3,9), (2,3,10), (3,4,7), (1,6,5), (4,5,6)]).solve() == vec![SENTINEL, 6, 3, 2, 5, 4, 1]);
+    /// the cheapest perfect matching, not the heaviest or the cheapest of any size
+    fn min_weight_perfect() {
+        assert!(min_weight_perfect_matching::<i32>(vec![]) == Ok((vec![], 0)));
+        assert!(min_weight_perfect_matching::<i32>(vec![(0,1,5), (1,2,1), (2,3,6), (0,3,2), (0,2,4), (1,3,4)]) == Ok((vec![(0, 3), (1, 2)], 3)));
+        assert!(min_weight_perfect_matching::<i32>(vec![(0,1,3), (1,2,1), (2,3,3)]) == Ok((vec![(0, 1), (2, 3)], 6)));
+        assert!(min_weight_perfect_matching::<i32>(vec![(0,1,-3), (1,2,7), (2,3,-4), (0,3,1)]) == Ok((vec![(0, 1), (2, 3)], -7)));
     }
 
     #[test]
-    /// create S-blossom, relabel as T-blossom, use for augmentation
-    fn s_t_relabel_augmentation() {
-        assert!(Matching::new(vec![(1,2,9), (1,3,8), (2,3,10), (1,4,5), (4,5,4), (1,6,3)]).solve() == vec![SENTINEL, 6, 3, 2, 5, 4, 1]);
-        assert!(Matching::new(vec![(1,2,9), (1,3,8), (2,3,10), (1,4,5), (4,5,3), (1,6,4)]).solve() == vec![SENTINEL, 6, 3, 2, 5, 4, 1]);
-        assert!(Matching::new(vec![(1,2,9), (1,3,8), (2,3,10), (1,4,5), (4,5,3), (3,6,4)]).solve() == vec![SENTINEL, 2, 1, 6, 5, 4, 3]);
+    /// odd number of vertices and an isolated vertex
+    fn not_perfect() {
+        assert!(min_weight_perfect_matching(vec![(0,1,2), (1,2,1)]) == Err(NotPerfect { pairs: vec![(1, 2)], unmatched: vec![0] }));
+        assert!(min_weight_perfect_matching(vec![(0,2,1), (2,3,5)]) == Err(NotPerfect { pairs: vec![(0, 2)], unmatched: vec![1, 3] }));
     }
 
     #[test]
-    /// create nested S-blossom, use for augmentation
-    fn s_nested_augmentation() {
-        assert!(Matching::new(vec![(1,2,9), (1,3,9), (2,3,10), (2,4,8), (3,5,8), (4,5,10), (5,6,6)]).solve() == vec![SENTINEL, 3, 4, 1, 2, 6, 5]);
+    /// weights beyond i32, as with coordinates up to a million
+    fn large_weights() {
+        let (a, b) = (3_000_000_000i64, 5_000_000_000i64);
+        assert!(Matching::new(vec![(0,1,a), (1,2,b), (2,3,a)]).solve() == vec![1, 0, 3, 2]);
+        assert!(min_weight_perfect_matching(vec![(0,1,b), (1,2,a), (2,3,b), (0,3,a)]) == Ok((vec![(0, 3), (1, 2)], 2 * a)));
     }
 
     #[test]
-    /// create S-blossom, relabel as S, include in nested S-blossom
-    fn s_relabel_nested() {
-        assert!(Matching::new(vec![(1,2,10), (1,7,10), (2,3,12), (3,4,20), (3,5,20), (4,5,25), (5,6,10), (6,7,10), (7,8,8)]).solve() == vec![SENTINEL, 2, 1, 4, 3, 6, 5, 8, 7]);
+    /// fractional weights, rounding them would pick the two outer edges
+    fn fractional_weights() {
+        assert!(Matching::new(vec![(0,1,0.6), (1,2,1.4), (2,3,0.6)]).solve() == vec![SENTINEL, 2, 1, SENTINEL]);
+        assert!(Matching::new(vec![(0,1,0.6), (1,2,1.4), (2,3,0.6)]).max_cardinality().solve() == vec![1, 0, 3, 2]);
+        assert!(min_weight_perfect_matching(vec![(0,1,0.4), (1,2,0.3), (2,3,0.4), (0,3,0.3)]) == Ok((vec![(0, 3), (1, 2)], 0.6)));
     }
 
-    #[test]
-    /// create nested S-blossom, augment, expand recursively
-    fn s_nested_expand_recursively() {
-        assert!(Matching::new(vec![(1,2,8), (1,3,8), (2,3,10), (2,4,12), (3,5,12), (4,5,14), (4,6,12), (5,7,12), (6,7,14), (7,8,12)]).solve() == vec![SENTINEL, 2, 1, 5, 6, 3, 4, 8, 7]);
+    // the heaviest matching by trying all of them
+    fn brute_force(n: usize, weights: &[Vec<Option<f64>>], matched: &mut Vec<bool>, maxcardinality: bool) -> (usize, f64) {
+        let Some(i) = (0..n).find(|&i| !matched[i]) else { return (0, 0.0) };
+        matched[i] = true;
+        let mut best = brute_force(n, weights, matched, maxcardinality);
+        for j in i + 1..n {
+            if let (false, Some(wt)) = (matched[j], weights[i][j]) {
+                matched[j] = true;
+                let (size, weight) = brute_force(n, weights, matched, maxcardinality);
+                matched[j] = false;
+                let better = if maxcardinality { (size + 1, weight + wt) > best } else { weight + wt > best.1 };
+                if better {
+                    best = (size + 1, weight + wt);
+                }
+            }
+        }
+        matched[i] = false;
+        best
     }
 
     #[test]
-    /// create S-blossom, relabel as T, expand
-    fn s_t_expand() {
-        assert!(Matching::new(vec![(1,2,23), (1,5,22), (1,6,15), (2,3,25), (3,4,22), (4,5,25), (4,8,14), (5,7,13)]).solve() == vec![SENTINEL, 6, 3, 2, 8, 7, 1, 5, 4 ]);
+    /// random graphs with float weights, the dual updates must not get
+    /// stuck on slacks that are zero only up to rounding
+    fn random_float_weights() {
+        let mut rng = SimpleRng::new(3);
+        for _ in 0..300 {
+            let n = rng.gen_range(2, 9);
+            let mut edges = Vec::new();
+            for i in 0..n {
+                for j in i + 1..n {
+                    if rng.next_f64() < 0.6 {
+                        edges.push((i, j, rng.next_f64() * 10.0 - 2.0));
+                    }
+                }
+            }
+            let mut weights = vec![vec![None; n]; n];
+            for &(i, j, wt) in &edges {
+                weights[i][j] = Some(wt);
+            }
+            if edges.is_empty() {
+                continue;
+            }
+            for maxcardinality in [false, true] {
+                let mut matching = Matching::new(edges.clone());
+                if maxcardinality {
+                    matching.max_cardinality();
+                }
+                let mate = matching.solve();
+                let size = mate.iter().filter(|&&v| v != SENTINEL).count() / 2;
+                let weight: f64 = edges.iter().filter(|&&(i, j, _)| mate[i] == j).map(|e| e.2).sum();
+                let (best_size, best_weight) = brute_force(n, &weights, &mut vec![false; n], maxcardinality);
+                if maxcardinality {
+                    assert_eq!(size, best_size);
+                }
+                assert!((weight - best_weight).abs() < 1e-9, "{:?}: {} vs {}", edges, weight, best_weight);
+            }
+        }
     }
 
     #[test]
-    /// create nested S-blossom, relabel as T, expand
-    fn s_nest_t_expand() {
-        assert!(Matching::new(vec![(1,2,19), (1,3,20), (1,8,8), (2,3,25), (2,4,18), (3,5,18), (4,5,13), (4,7,7), (5,6,7)]).solve() == vec![SENTINEL, 8, 3, 2, 7, 6, 5, 4, 1 ]);
+    /// the duals certify the optimum: the dual objective is the weight
+    fn duals_certify_optimum() {
+        let edges = vec![(1,2,40), (1,3,40), (2,3,60), (2,4,55), (3,5,55), (4,5,50), (1,8,15), (5,7,30), (7,6,10), (8,10,10), (4,9,30)];
+        let mut matching = Matching::new(edges.clone());
+        let mate = matching.solve();
+        assert!(matching.verify().is_optimal());
+        let duals = matching.duals();
+        let weight: i32 = edges.iter().filter(|&&(i, j, _)| mate[i] == j).map(|e| e.2).sum();
+        assert_eq!(duals.objective(), 2 * weight as i64);
+        assert_eq!(duals.vertex_offset, 0);
+        for b in &duals.blossoms {
+            assert!(b.vertices.len() % 2 == 1 && b.vertices.contains(&b.base));
+            assert!(b.parent == SENTINEL || duals.blossoms.iter().any(|p| p.id == b.parent));
+        }
+
+        let mut matching = Matching::new(vec![(0,1,0.6), (1,2,1.4), (2,3,0.6), (0,2,0.9), (1,3,0.2)]);
+        matching.max_cardinality().solve();
+        assert!(matching.verify().is_optimal());
+        // perfect, so the bound holds despite max_cardinality
+        assert!((matching.duals().objective() - 2.0 * 1.2).abs() < 1e-9);
     }
 
     #[test]
-    /// create blossom, relabel as T in more than one way, expand, augment
-    fn tnasty_expand() {
-        assert!(Matching::new(vec![(1,2,45), (1,5,45), (2,3,50), (3,4,45), (4,5,50), (1,6,30), (3,9,35), (4,8,35), (5,7,26), (9,10,5)]).solve() == vec![SENTINEL, 6, 3, 2, 8, 7, 1, 5, 4, 10, 9 ]);
+    /// a broken dual solution is reported instead of panicking
+    fn verify_reports_violations() {
+        let mut matching = Matching::new(vec![(0,1,5), (1,2,8)]);
+        assert!(matching.solve() == vec![SENTINEL, 2, 1]);
+        assert!(matching.verify().is_optimal());
+        matching.dualvar[0] += 2;
+        matching.dualvar[1] -= 4;
+        let report = matching.verify();
+        assert!(report.violations.contains(&Violation::NegativeSlack { edge: 1, slack: -4 }));
+        assert!(report.violations.contains(&Violation::SingleDual { vertex: 0, dual: 2 }));
     }
 
-    #[test]
-    /// again but slightly different
-    fn tnasty2_expand() {
-        assert!(Matching::new(vec![(1,2,45), (1,5,45), (2,3,50), (3,4,45), (4,5,50), (1,6,30), (3,9,35), (4,8,26), (5,7,40), (9,10,5)]).solve() == vec![SENTINEL, 6, 3, 2, 8, 7, 1, 5, 4, 10, 9 ]);
+    fn random_complete(n: usize, rng: &mut SimpleRng) -> Edges {
+        let mut edges = vec![];
+        for i in 0..n {
+            for j in i + 1..n {
+                edges.push((i, j, rng.gen_range(0, 1000) as i32));
+            }
+        }
+        edges
     }
 
-    #[test]
-    /// create blossom, relabel as T, expand such that a new least-slack S-to-free edge is produced, augment
-    fn t_expand_leastslack() {
-        assert!(Matching::new(vec![(1,2,45), (1,5,45), (2,3,50), (3,4,45), (4,5,50), (1,6,30), (3,9,35), (4,8,28), (5,7,26), (9,10,5)]).solve() == vec![SENTINEL, 6, 3, 2, 8, 7, 1, 5, 4, 10, 9 ]);
+    fn mate_weight(edges: &Edges, mate: &Vertices) -> i32 {
+        edges.iter().filter(|&&(i, j, _)| mate.get(i) == Some(&j)).map(|e| e.2).sum()
     }
 
     #[test]
-    /// create nested blossom, relabel as T in more than one way, expand outer blossom such that inner blossom ends up on an augmenting path
-    fn nest_tnasty_expand() {
-        assert!(Matching::new(vec![(1,2,45), (1,7,45), (2,3,50), (3,4,45), (4,5,95), (4,6,94), (5,6,94), (6,7,50), (1,8,30), (3,11,35), (5,9,36), (7,10,26), (11,12,5)]).solve() == vec![SENTINEL, 8, 3, 2, 6, 9, 4, 10, 1, 5, 7, 12, 11]);
+    /// any warm start reaches the optimum, the optimum itself right away
+    fn warm_start_reaches_optimum() {
+        let mut rng = SimpleRng::new(9);
+        let edges = random_complete(30, &mut rng);
+        let mut cold = Matching::new(edges.clone());
+        let mate = cold.max_cardinality().solve();
+        let optimum = mate_weight(&edges, &mate);
+        let flat = cold.flat_duals();
+
+        let mut warm = Matching::new(edges.clone());
+        assert!(warm.warm_start(&mate, &flat).solve() == mate);
+        assert!(warm.verify().is_optimal());
+        // nothing at all, and a matching whose pairs are split for slack
+        let shifted: Vertices = (0..30).map(|v| v ^ 1).collect();
+        for (mate, dualvar) in [(vec![], vec![]), (shifted, vec![0; 30])] {
+            let mut warm = Matching::new(edges.clone());
+            let result = warm.warm_start(&mate, &dualvar).solve();
+            assert!(result.iter().all(|&w| w != SENTINEL));
+            assert!(warm.verify().is_optimal());
+            assert_eq!(mate_weight(&edges, &result), optimum);
+        }
     }
 
     #[test]
-    /// create nested S-blossom, relabel as S, expand recursively
-    fn nest_relabel_expand() {
-        assert!(Matching::new(vec![(1,2,40), (1,3,40), (2,3,60), (2,4,55), (3,5,55), (4,5,50), (1,8,15), (5,7,30), (7,6,10), (8,10,10), (4,9,30)]).solve() == vec![SENTINEL, 2, 1, 5, 9, 3, 7, 6, 10, 4, 8 ]);
+    /// changing a few vertices at a time gives the optimum of the new graph
+    fn incremental_matches_scratch() {
+        let mut rng = SimpleRng::new(10);
+        let n = 40;
+        let mut weights: Vec<Vec<i32>> = (0..n).map(|_| (0..n).map(|_| rng.gen_range(0, 1000) as i32).collect()).collect();
+        let weight = |weights: &Vec<Vec<i32>>, i: usize, j: usize| weights[i.min(j)][i.max(j)];
+        let mut edges = vec![];
+        for i in 0..n {
+            for j in i + 1..n {
+                edges.push((i, j, weight(&weights, i, j)));
+            }
+        }
+        let mut incremental = IncrementalMatching::new(edges);
+        let mut present: Vec<usize> = (0..n).collect();
+        for round in 0..10 {
+            for _ in 0..2 {
+                let v = present.swap_remove(rng.gen_range(0, present.len()));
+                incremental.remove_vertex(v);
+            }
+            for _ in 0..2 {
+                // takes the smallest free number, new weights for it
+                let v = (0..n).find(|v| !present.contains(v)).unwrap();
+                let neighbors: Vec<(Vertex, i32)> = present.iter().map(|&u| (u, weight(&weights, u, v) + round)).collect();
+                assert_eq!(incremental.add_vertex(&neighbors), v);
+                for &(u, wt) in &neighbors {
+                    weights[u.min(v)][u.max(v)] = wt;
+                }
+                present.push(v);
+            }
+            let mate = incremental.solve().clone();
+
+            // the same graph from scratch, numbered consecutively
+            present.sort();
+            let mut scratch = vec![];
+            for (a, &i) in present.iter().enumerate() {
+                for (b, &j) in present.iter().enumerate().skip(a + 1) {
+                    scratch.push((a, b, weight(&weights, i, j)));
+                }
+            }
+            let expected = Matching::new(scratch.clone()).max_cardinality().solve();
+            let found: i32 = present.iter().filter(|&&v| mate[v] > v).map(|&v| weight(&weights, v, mate[v])).sum();
+            assert!(present.iter().all(|&v| mate[v] != SENTINEL && present.contains(&mate[v])));
+            assert_eq!(found, mate_weight(&scratch, &expected));
+        }
     }
-}
\ No newline at end of file
+}
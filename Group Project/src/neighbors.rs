@@ -0,0 +1,54 @@
+use crate::distance::DistanceOracle;
+
+// For every node the k closest other nodes, sorted by increasing distance.
+// Local search only tries moves whose new edge connects a node to one of
+// its candidates, which turns the O(n^2) / O(n^3) scans into O(n k) / O(n k^2).
+pub struct CandidateList {
+    neighbors: Vec<Vec<i32>>,
+}
+
+impl CandidateList {
+    pub(crate) fn new<D: DistanceOracle>(graph: &D, k: usize) -> CandidateList {
+        let n = graph.num_nodes();
+        let k = k.min(n.saturating_sub(1));
+        let mut neighbors = Vec::with_capacity(n);
+        let mut others: Vec<i32> = Vec::with_capacity(n);
+        for x in 0..n as i32 {
+            others.clear();
+            others.extend((0..n as i32).filter(|&y| y != x));
+            if k < others.len() {
+                others.select_nth_unstable_by_key(k, |&y| graph.get_edge(x, y));
+                others.truncate(k);
+            }
+            others.sort_by_key(|&y| graph.get_edge(x, y));
+            neighbors.push(others.clone());
+        }
+        CandidateList { neighbors }
+    }
+
+    // lists that are already sorted by distance, e.g. from a sparse graph
+    pub(crate) fn from_neighbors(neighbors: Vec<Vec<i32>>) -> CandidateList {
+        CandidateList { neighbors }
+    }
+
+    pub(crate) fn get(&self, node: i32) -> &[i32] {
+        &self.neighbors[node as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Graph;
+
+    #[test]
+    fn nearest_first() {
+        let points = vec![(0.0, 0.0), (10.0, 0.0), (1.0, 0.0), (3.0, 0.0), (6.0, 0.0)];
+        let graph = Graph::with_metric(&points, crate::metric::Metric::Euc2d);
+        let candidates = CandidateList::new(&graph, 3);
+        assert_eq!(candidates.get(0), &[2, 3, 4]);
+        assert_eq!(candidates.get(1), &[4, 3, 2]);
+        // k is capped at n - 1
+        assert_eq!(CandidateList::new(&graph, 10).get(3).len(), 4);
+    }
+}
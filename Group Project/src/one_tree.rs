@@ -0,0 +1,394 @@
+// Held–Karp lower bound on the tour length.
+//
+// A 1-tree is a spanning tree plus one more edge at a leaf; every tour is a
+// 1-tree, so the minimum 1-tree is a lower bound. Adding a penalty pi(x) to
+// both ends of every edge at x adds 2 pi(x) to every tour but changes which
+// 1-tree is minimal, so 1-tree - 2 sum(pi) is a lower bound for any pi. The
+// subgradient method raises the penalties of the leaves and lowers those of
+// the nodes of degree above two until the 1-tree is close to a tour. The
+// same 1-tree, with or without the penalties, ranks the candidate edges of
+// the local search by alpha-nearness.
+
+use std::time::Instant;
+use crate::distance::DistanceOracle;
+use crate::neighbors::CandidateList;
+use crate::sparse_graph::SparseGraph;
+
+// step scale of the subgradient method, halved whenever the bound stalls
+const INITIAL_LAMBDA: f64 = 2.0;
+// stop once the steps are this small
+const MIN_LAMBDA: f64 = 1e-3;
+
+// How the candidate lists of the local search are chosen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CandidateSet {
+    // the k closest nodes
+    Nearest,
+    // the k smallest alpha-nearness values, on distances penalized by
+    // ascent_ms of subgradient optimization first unless it is 0
+    Alpha { ascent_ms: u128 },
+}
+
+pub struct OneTreeBound {
+    // no tour is shorter
+    pub bound: i64,
+    // the penalties of the best bound, one per node
+    pub penalties: Vec<i32>,
+    // number of 1-trees computed
+    pub iterations: usize,
+}
+
+impl OneTreeBound {
+    // how much longer the tour is than the bound, as a fraction of the bound
+    pub fn gap(&self, tour_length: i64) -> f64 {
+        if self.bound <= 0 {
+            return 0.0;
+        }
+        (tour_length - self.bound) as f64 / self.bound as f64
+    }
+}
+
+// The distances with the penalties of both ends added.
+pub struct Penalized<'a, D> {
+    pub graph: &'a D,
+    pub penalties: &'a [i32],
+}
+
+impl<D: DistanceOracle> DistanceOracle for Penalized<'_, D> {
+    fn num_nodes(&self) -> usize {
+        self.graph.num_nodes()
+    }
+
+    fn get_edge(&self, x: i32, y: i32) -> i32 {
+        self.graph.get_edge(x, y) + self.penalties[x as usize] + self.penalties[y as usize]
+    }
+}
+
+pub struct OneTree {
+    // the minimum spanning tree, without the extra edge
+    pub tree: SparseGraph,
+    // the leaf the extra edge starts from and the node it goes to
+    pub special: (i32, i32),
+    pub length: i64,
+}
+
+impl OneTree {
+    pub fn degree(&self, x: i32) -> i32 {
+        self.tree.get_vertex_degree(x) + (x == self.special.0) as i32 + (x == self.special.1) as i32
+    }
+}
+
+// Minimum spanning tree plus the second shortest edge of one of its leaves.
+// A leaf's tree edge is its shortest, so this is the minimum 1-tree with the
+// leaf as the special node; the leaf whose second edge is longest gives the
+// best bound.
+pub fn one_tree<D: DistanceOracle>(graph: &D) -> OneTree {
+    let n = graph.num_nodes();
+    let tree = graph.get_min_spanning_tree();
+    let mut length: i64 = (0..n as i32)
+        .flat_map(|x| tree.adjacency_list[x as usize].iter().filter(move |&&y| x < y).map(move |&y| (x, y)))
+        .map(|(x, y)| graph.get_edge(x, y) as i64)
+        .sum();
+    let mut special = (0, 0);
+    let mut best = i32::MIN;
+    for x in 0..n as i32 {
+        if tree.get_vertex_degree(x) != 1 {
+            continue;
+        }
+        let neighbor = tree.adjacency_list[x as usize][0];
+        let second = (0..n as i32)
+            .filter(|&y| y != x && y != neighbor)
+            .map(|y| (graph.get_edge(x, y), y))
+            .min();
+        if let Some((edge, y)) = second {
+            if edge > best {
+                best = edge;
+                special = (x, y);
+            }
+        }
+    }
+    if best != i32::MIN {
+        length += best as i64;
+    }
+    OneTree { tree, special, length }
+}
+
+// Subgradient optimization of the penalties, using the length of a known
+// tour as the target of the steps. Stops when the 1-tree is a tour, the
+// steps got too small or the time is up.
+pub fn held_karp_bound<D: DistanceOracle>(graph: &D, upper_bound: i64, start_time: Instant, max_processing_ms: u128) -> OneTreeBound {
+    let n = graph.num_nodes();
+    let mut penalties = vec![0; n];
+    let mut result = OneTreeBound { bound: i64::MIN, penalties: penalties.clone(), iterations: 0 };
+    if n < 3 {
+        result.bound = upper_bound;
+        return result;
+    }
+    // halve the steps after this many iterations without a better bound
+    let period = (n / 2).clamp(10, 100);
+    let mut lambda = INITIAL_LAMBDA;
+    let mut stalled = 0;
+    loop {
+        let tree = one_tree(&Penalized { graph, penalties: &penalties });
+        result.iterations += 1;
+        let bound = tree.length - 2 * penalties.iter().map(|&p| p as i64).sum::<i64>();
+        if bound > result.bound {
+            result.bound = bound;
+            result.penalties.copy_from_slice(&penalties);
+            stalled = 0;
+        } else {
+            stalled += 1;
+            if stalled >= period {
+                lambda /= 2.0;
+                stalled = 0;
+            }
+        }
+        let excess: Vec<i32> = (0..n as i32).map(|x| tree.degree(x) - 2).collect();
+        let norm: i64 = excess.iter().map(|&d| (d * d) as i64).sum();
+        if norm == 0 || lambda < MIN_LAMBDA || start_time.elapsed().as_millis() >= max_processing_ms {
+            break;
+        }
+        // Polyak's step towards the tour length, at least one unit
+        let step = (lambda * (upper_bound - bound).max(0) as f64 / norm as f64).round().max(1.0) as i32;
+        for (penalty, &d) in penalties.iter_mut().zip(&excess) {
+            *penalty += step * d;
+        }
+    }
+    result
+}
+
+// For every node the k other nodes with the smallest alpha-nearness, the
+// amount the minimum 1-tree grows if the edge to them is forced in: the edge
+// minus the longest edge on the tree path it closes. Optimal tours mostly
+// use edges with small alpha, even where they are far from the shortest
+// (between clusters). With Held–Karp penalties the alphas are computed on the
+// penalized distances, which makes them much more selective.
+// Like the nearest neighbor lists, each list is sorted by distance, which the
+// local searches rely on to stop early: two_opt, three_opt and both Lins.
+// O(n^2) time, O(n) memory on top.
+pub fn alpha_candidates<D: DistanceOracle>(graph: &D, penalties: Option<&[i32]>, k: usize) -> CandidateList {
+    let n = graph.num_nodes();
+    let zeros = vec![0; n];
+    let penalized = Penalized { graph, penalties: penalties.unwrap_or(&zeros) };
+    let k = k.min(n.saturating_sub(1));
+    if n < 3 {
+        return CandidateList::new(graph, k);
+    }
+    let tree = one_tree(&penalized);
+    let (leaf, extra) = tree.special;
+    // the two 1-tree edges at the special leaf, the longer one is replaced
+    // by any other edge there
+    let anchor = tree.tree.adjacency_list[leaf as usize][0];
+    let leaf_longest = penalized.get_edge(leaf, extra).max(penalized.get_edge(leaf, anchor));
+
+    // the tree rooted at the leaf; its paths between the other nodes do not
+    // go through it
+    let mut parent = vec![-1; n];
+    let mut order = vec![leaf];
+    parent[leaf as usize] = leaf;
+    let mut head = 0;
+    while head < order.len() {
+        let x = order[head];
+        head += 1;
+        for &y in &tree.tree.adjacency_list[x as usize] {
+            if parent[y as usize] == -1 {
+                parent[y as usize] = x;
+                order.push(y);
+            }
+        }
+    }
+
+    // longest tree edge on the path from x to every other node: set along
+    // the path to the root first, then down from the root for the rest
+    let mut longest = vec![i32::MIN; n];
+    let mut on_path = vec![usize::MAX; n];
+    let mut alphas: Vec<(i32, i32, i32)> = Vec::with_capacity(n);
+    let mut neighbors = Vec::with_capacity(n);
+    for x in 0..n as i32 {
+        alphas.clear();
+        if x == leaf {
+            for y in (0..n as i32).filter(|&y| y != leaf) {
+                let alpha = if y == extra || y == anchor { 0 } else { penalized.get_edge(leaf, y) - leaf_longest };
+                alphas.push((alpha, graph.get_edge(x, y), y));
+            }
+        } else {
+            longest[x as usize] = i32::MIN;
+            on_path[x as usize] = x as usize;
+            let mut y = x;
+            while parent[y as usize] != leaf {
+                let p = parent[y as usize];
+                longest[p as usize] = longest[y as usize].max(penalized.get_edge(y, p));
+                on_path[p as usize] = x as usize;
+                y = p;
+            }
+            for &y in &order[1..] {
+                if on_path[y as usize] != x as usize {
+                    let p = parent[y as usize];
+                    longest[y as usize] = longest[p as usize].max(penalized.get_edge(y, p));
+                }
+                if y != x {
+                    alphas.push((penalized.get_edge(x, y) - longest[y as usize], graph.get_edge(x, y), y));
+                }
+            }
+            let alpha = if x == extra || x == anchor { 0 } else { penalized.get_edge(leaf, x) - leaf_longest };
+            alphas.push((alpha, graph.get_edge(x, leaf), leaf));
+        }
+        if k < alphas.len() {
+            alphas.select_nth_unstable(k);
+            alphas.truncate(k);
+        }
+        alphas.sort_unstable_by_key(|&(_, length, y)| (length, y));
+        neighbors.push(alphas.iter().map(|&(_, _, y)| y).collect());
+    }
+    CandidateList::from_neighbors(neighbors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use crate::utils::{DisjointSet, Graph, SimpleRng, tour_length};
+
+    // shortest tour by trying all orders after node 0
+    fn brute_force(graph: &Graph) -> i64 {
+        fn search(graph: &Graph, tour: &mut Vec<i32>, used: &mut [bool], best: &mut i64) {
+            let n = graph.num_nodes();
+            if tour.len() == n {
+                *best = (*best).min(tour_length(graph, tour));
+                return;
+            }
+            for y in 1..n {
+                if !used[y] {
+                    used[y] = true;
+                    tour.push(y as i32);
+                    search(graph, tour, used, best);
+                    tour.pop();
+                    used[y] = false;
+                }
+            }
+        }
+        let mut best = i64::MAX;
+        search(graph, &mut vec![0], &mut vec![false; graph.num_nodes()], &mut best);
+        best
+    }
+
+    #[test]
+    fn one_tree_is_a_lower_bound() {
+        let mut rng = SimpleRng::new(3);
+        for n in [3, 5, 8] {
+            let points: Vec<(f64, f64)> = (0..n).map(|_| (rng.next_f64() * 100.0, rng.next_f64() * 100.0)).collect();
+            let graph = Graph::with_metric(&points, Metric::Euc2d);
+            let tree = one_tree(&graph);
+            assert_eq!((0..n as i32).map(|x| tree.degree(x)).sum::<i32>(), 2 * n as i32);
+            let optimum = brute_force(&graph);
+            let result = held_karp_bound(&graph, optimum, Instant::now(), 200);
+            assert!(tree.length <= result.bound);
+            assert!(result.bound <= optimum);
+            assert_eq!(result.penalties.len(), n);
+        }
+    }
+
+    #[test]
+    fn exact_on_a_grid() {
+        // every tour of unit steps is optimal and the penalties find one
+        let points: Vec<(f64, f64)> = (0..16).map(|i| ((i % 4 * 10) as f64, (i / 4 * 10) as f64)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let result = held_karp_bound(&graph, 200, Instant::now(), 500);
+        assert_eq!(result.bound, 160);
+        assert_eq!(result.gap(160), 0.0);
+    }
+
+    #[test]
+    fn small_gap_on_random_points() {
+        let mut rng = SimpleRng::new(11);
+        let points: Vec<(f64, f64)> = (0..200).map(|_| (rng.next_f64() * 10000.0, rng.next_f64() * 10000.0)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let mut tour: Vec<i32> = (0..200).collect();
+        tour.sort_by_key(|&x| (points[x as usize].0 / 1000.0) as i32 * 20000 + points[x as usize].1 as i32);
+        let length = tour_length(&graph, &tour);
+        // the steps get too small long before the time is up, also without
+        // optimizations, so the result does not depend on the machine
+        let result = held_karp_bound(&graph, length, Instant::now(), 20000);
+        assert!(result.iterations < 2000);
+        assert!(result.bound < length);
+        // the 1-tree without penalties is well below the optimum, the
+        // Held–Karp bound usually within one percent of it
+        assert!(result.bound as f64 > one_tree(&graph).length as f64 * 1.05);
+    }
+
+    // alpha from its definition: a minimum 1-tree with the edge forced in,
+    // with Kruskal on the other nodes and the special leaf added last
+    fn brute_force_alpha(graph: &Penalized<Graph>, special: i32, x: i32, y: i32) -> i64 {
+        let n = graph.num_nodes() as i32;
+        let forced_one_tree = |forced: Option<(i32, i32)>| {
+            let mut edges: Vec<(i32, i32, i32)> = (0..n).flat_map(|a| (a + 1..n).map(move |b| (a, b)))
+                .filter(|&(a, b)| a != special && b != special)
+                .map(|(a, b)| (graph.get_edge(a, b), a, b))
+                .collect();
+            edges.sort();
+            let mut components = DisjointSet::new(n as usize);
+            let mut length = 0;
+            let mut at_special: Vec<i32> = (0..n).filter(|&b| b != special).map(|b| graph.get_edge(special, b)).collect();
+            at_special.sort();
+            match forced {
+                Some((a, b)) if a == special || b == special => {
+                    let edge = graph.get_edge(a, b);
+                    let i = at_special.iter().position(|&e| e == edge).unwrap();
+                    at_special.remove(i);
+                    length += (edge + at_special[0]) as i64;
+                }
+                Some((a, b)) => {
+                    components.union(a, b);
+                    length += (graph.get_edge(a, b) + at_special[0] + at_special[1]) as i64;
+                }
+                None => length += (at_special[0] + at_special[1]) as i64,
+            }
+            for (edge, a, b) in edges {
+                if components.union(a, b) {
+                    length += edge as i64;
+                }
+            }
+            length
+        };
+        forced_one_tree(Some((x, y))) - forced_one_tree(None)
+    }
+
+    #[test]
+    fn alpha_matches_definition() {
+        let mut rng = SimpleRng::new(5);
+        for n in [4, 7, 12] {
+            let points: Vec<(f64, f64)> = (0..n).map(|_| (rng.next_f64() * 1000.0, rng.next_f64() * 1000.0)).collect();
+            let graph = Graph::with_metric(&points, Metric::Euc2d);
+            let penalties: Vec<i32> = (0..n).map(|_| rng.gen_range(0, 100) as i32 - 50).collect();
+            for penalties in [None, Some(&penalties[..])] {
+                let penalized = Penalized { graph: &graph, penalties: penalties.unwrap_or(&[0; 12][..n]) };
+                let special = one_tree(&penalized).special.0;
+                let k = 3;
+                let candidates = alpha_candidates(&graph, penalties, k);
+                for x in 0..n as i32 {
+                    let mut expected: Vec<(i64, i32, i32)> = (0..n as i32).filter(|&y| y != x)
+                        .map(|y| (brute_force_alpha(&penalized, special, x, y), graph.get_edge(x, y), y))
+                        .collect();
+                    expected.sort();
+                    let mut nearest: Vec<(i32, i32)> = expected[..k].iter().map(|&(_, length, y)| (length, y)).collect();
+                    nearest.sort();
+                    assert_eq!(candidates.get(x), nearest.iter().map(|&(_, y)| y).collect::<Vec<i32>>());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alpha_finds_the_edges_between_clusters() {
+        // two clusters of ten nodes: the k nearest neighbors of every node
+        // stay inside its cluster, the alpha-nearest ones do not
+        let mut rng = SimpleRng::new(9);
+        let points: Vec<(f64, f64)> = (0..20)
+            .map(|i| ((i / 10 * 1000) as f64 + rng.next_f64() * 50.0, rng.next_f64() * 50.0))
+            .collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let between = |candidates: &CandidateList| (0..20).any(|x| candidates.get(x).iter().any(|&y| (x < 10) != (y < 10)));
+        assert!(!between(&CandidateList::new(&graph, 5)));
+        assert!(between(&alpha_candidates(&graph, None, 5)));
+    }
+}
@@ -0,0 +1,230 @@
+use std::time::Instant;
+use crate::distance::DistanceOracle;
+use crate::neighbors::CandidateList;
+use crate::tour::Tour;
+use crate::utils::{SimpleRng, tour_length};
+
+// longest segment an Or-opt move relocates
+const SEGMENT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cooling {
+    // multiply the temperature by alpha after every step
+    Geometric { alpha: f64 },
+    // lower the temperature linearly to zero over the time budget
+    Linear,
+    // geometric, but go back to reheat * the start temperature after
+    // `patience` steps without a new best tour
+    Reheating { alpha: f64, patience: usize, reheat: f64 },
+}
+
+impl Cooling {
+    pub fn from_name(name: &str) -> Option<Cooling> {
+        match name {
+            "geometric" => Some(Cooling::Geometric { alpha: 0.95 }),
+            "linear" => Some(Cooling::Linear),
+            "reheating" => Some(Cooling::Reheating { alpha: 0.95, patience: 10, reheat: 0.3 }),
+            _ => None,
+        }
+    }
+}
+
+pub struct Schedule {
+    pub cooling: Cooling,
+    // None starts at the average edge length of the initial tour
+    pub initial_temperature: Option<f64>,
+    // the time budget is split into this many temperature steps of equal
+    // length, however many moves fit into one at the given n
+    pub steps: usize,
+}
+
+impl Schedule {
+    pub fn new(cooling: Cooling) -> Schedule {
+        Schedule { cooling, initial_temperature: None, steps: 100 }
+    }
+}
+
+// Simulated annealing on random 2-opt and Or-opt moves whose new edge joins a
+// node to one of its candidates. Every move is evaluated in O(1) from the
+// removed and added edges; the length of the current tour is tracked
+// incrementally. Returns the best tour seen, which is copied only before the
+// first move that makes it longer again.
+pub fn simulated_annealing<D: DistanceOracle>(graph: &D, candidates: &CandidateList, tour: Vec<i32>, schedule: &Schedule, seed: u64, start_time: Instant, max_processing_ms: u128) -> Vec<i32> {
+    let n = tour.len();
+    if n < 2 * SEGMENT + 2 {
+        return tour;
+    }
+    let mut rng = SimpleRng::new(seed);
+    let mut length = tour_length(graph, &tour);
+    let mut tour = Tour::new(tour);
+    let mut best = tour.clone();
+    let mut best_length = length;
+    // the current tour is a new best that is not copied to best yet
+    let mut at_best = false;
+
+    let initial_temperature = schedule.initial_temperature.unwrap_or(length as f64 / n as f64);
+    let mut temperature = initial_temperature;
+    let anneal_start = start_time.elapsed().as_millis();
+    let step_ms = (max_processing_ms.saturating_sub(anneal_start) / schedule.steps.max(1) as u128).max(1);
+    let mut step_end = anneal_start;
+    let mut steps_without_best = 0;
+
+    while start_time.elapsed().as_millis() < max_processing_ms {
+        let mut new_best = false;
+        step_end = (step_end + step_ms).min(max_processing_ms);
+        for i in 0usize.. {
+            // checking the time on every move is too expensive
+            if i % 1024 == 0 && start_time.elapsed().as_millis() >= step_end {
+                break;
+            }
+            let a = rng.gen_range(0, n) as i32;
+            let c = candidates.get(a)[rng.gen_range(0, candidates.get(a).len())];
+            let forward = rng.gen_range(0, 2) == 0;
+            let step = if rng.gen_range(0, 2) == 0 {
+                try_2opt(graph, &tour, a, c, forward)
+            } else {
+                let segment = rng.gen_range(1, SEGMENT + 1);
+                try_or_opt(graph, &tour, a, c, forward, segment)
+            };
+            let Some((delta, step)) = step else { continue };
+            if !accept(&mut rng, delta, temperature) {
+                continue;
+            }
+            if delta > 0 && at_best {
+                best = tour.clone();
+                at_best = false;
+            }
+            step.apply(&mut tour);
+            length += delta as i64;
+            if length < best_length {
+                best_length = length;
+                at_best = true;
+                new_best = true;
+            }
+        }
+        debug_assert_eq!(length, tour_length(graph, tour.order()), "tracked tour length drifted");
+
+        if new_best {
+            steps_without_best = 0;
+        } else {
+            steps_without_best += 1;
+        }
+
+        temperature = match schedule.cooling {
+            Cooling::Geometric { alpha } => temperature * alpha,
+            Cooling::Linear => {
+                let elapsed = (start_time.elapsed().as_millis() - anneal_start) as f64;
+                let budget = max_processing_ms.saturating_sub(anneal_start).max(1) as f64;
+                initial_temperature * (1.0 - elapsed / budget).max(0.0)
+            }
+            Cooling::Reheating { alpha, patience, reheat } => {
+                if steps_without_best >= patience {
+                    steps_without_best = 0;
+                    initial_temperature * reheat
+                } else {
+                    temperature * alpha
+                }
+            }
+        };
+    }
+    if at_best { tour.into_vec() } else { best.into_vec() }
+}
+
+// Metropolis criterion: improvements are always taken, a move making the
+// tour longer by delta with probability exp(-delta / T).
+fn accept(rng: &mut SimpleRng, delta: i32, temperature: f64) -> bool {
+    delta <= 0 || (temperature > 0.0 && rng.next_f64() < (-(delta as f64) / temperature).exp())
+}
+
+// A move on the tour, applied once it is accepted.
+enum Step {
+    TwoOpt(i32, i32, i32, i32),
+    Segment(i32, i32, i32, i32),
+}
+
+impl Step {
+    fn apply(self, tour: &mut Tour) {
+        match self {
+            Step::TwoOpt(a, b, c, d) => tour.move_2opt(a, b, c, d),
+            Step::Segment(u, v, c, d) => tour.move_segment(u, v, c, d),
+        }
+    }
+}
+
+// 2-opt move replacing (a, b) and (c, d) by (a, c) and (b, d), where b and d
+// follow a and c in the given direction. Returns the change in length and
+// the move, if there is one.
+fn try_2opt<D: DistanceOracle>(graph: &D, tour: &Tour, a: i32, c: i32, forward: bool) -> Option<(i32, Step)> {
+    let b = tour.succ(a, forward);
+    let d = tour.succ(c, forward);
+    if c == b || d == a {
+        return None;
+    }
+    let delta = graph.get_edge(a, c) + graph.get_edge(b, d) - graph.get_edge(a, b) - graph.get_edge(c, d);
+    Some((delta, Step::TwoOpt(a, b, c, d)))
+}
+
+// Or-opt move taking the segment of the given length starting at u and
+// inserting it between c and its successor (in the given direction), with
+// u next to c. Returns the change in length and the move, if there is one.
+fn try_or_opt<D: DistanceOracle>(graph: &D, tour: &Tour, u: i32, c: i32, forward: bool, segment: usize) -> Option<(i32, Step)> {
+    let mut v = u;
+    for _ in 1..segment {
+        v = tour.succ(v, forward);
+    }
+    let in_segment = |x: i32| {
+        let steps = if forward { tour.distance(u, x) } else { tour.distance(x, u) };
+        steps < segment
+    };
+    let d = tour.succ(c, forward);
+    if in_segment(c) || in_segment(d) {
+        return None;
+    }
+    let (pu, pv) = (tour.succ(u, !forward), tour.succ(v, forward));
+    let delta = graph.get_edge(pu, pv) + graph.get_edge(u, c) + graph.get_edge(v, d)
+        - graph.get_edge(pu, u) - graph.get_edge(v, pv) - graph.get_edge(c, d);
+    Some((delta, Step::Segment(u, v, c, d)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::Metric;
+    use crate::utils::Graph;
+
+    fn run(cooling: Cooling) -> (i64, i64, Vec<i32>) {
+        let mut rng = SimpleRng::new(5);
+        let points: Vec<(f64, f64)> = (0..150).map(|_| (rng.next_f64() * 1000.0, rng.next_f64() * 1000.0)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let candidates = CandidateList::new(&graph, 8);
+        let tour: Vec<i32> = (0..150).collect();
+        let before = tour_length(&graph, &tour);
+        let schedule = Schedule { cooling, initial_temperature: None, steps: 30 };
+        let tour = simulated_annealing(&graph, &candidates, tour, &schedule, 3, Instant::now(), 150);
+        (before, tour_length(&graph, &tour), tour)
+    }
+
+    #[test]
+    fn every_schedule_improves() {
+        for name in ["geometric", "linear", "reheating"] {
+            let (before, after, tour) = run(Cooling::from_name(name).unwrap());
+            assert!(after < before, "{} did not improve", name);
+            let mut sorted = tour.clone();
+            sorted.sort();
+            assert_eq!(sorted, (0..150).collect::<Vec<i32>>());
+        }
+    }
+
+    #[test]
+    fn or_opt_delta_matches_length() {
+        let points: Vec<(f64, f64)> = (0..10).map(|i| ((i * 37 % 11) as f64 * 10.0, (i * 13 % 7) as f64 * 10.0)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        for (u, c, forward, segment) in [(2, 7, true, 3), (2, 7, false, 2), (9, 0, true, 1), (4, 8, false, 3)] {
+            let mut tour = Tour::new((0..10).collect());
+            let before = tour_length(&graph, tour.order());
+            let (delta, step) = try_or_opt(&graph, &tour, u, c, forward, segment).unwrap();
+            step.apply(&mut tour);
+            assert_eq!(tour_length(&graph, tour.order()), before + delta as i64);
+        }
+    }
+}
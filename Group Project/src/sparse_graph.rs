@@ -0,0 +1,28 @@
+// Adjacency lists of an undirected multigraph: spanning trees, and the
+// Eulerian multigraphs built on them.
+
+pub struct SparseGraph {
+    pub(crate) adjacency_list: Vec<Vec<i32>>
+}
+
+impl SparseGraph {
+    pub(crate) fn new(num_nodes: i32) -> SparseGraph {
+        SparseGraph {
+            adjacency_list: vec![Vec::new(); num_nodes as usize]
+        }
+    }
+
+    pub(crate) fn add_edge(&mut self, x: i32, y: i32) {
+        self.adjacency_list[x as usize].push(y);
+        self.adjacency_list[y as usize].push(x);
+    }
+
+    pub(crate) fn get_vertex_degree(&self, x: i32) -> i32 {
+        self.adjacency_list[x as usize].len() as i32
+    }
+
+    #[cfg(test)]
+    pub(crate) fn get_neighbors(&self, x: i32) -> Vec<i32> {
+        self.adjacency_list[x as usize].clone()
+    }
+}
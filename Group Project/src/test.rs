@@ -4,12 +4,17 @@ mod tests {
     use log::info;
     use rand::{Rng, SeedableRng};
     use rand::rngs::StdRng;
-    use crate::{christofidis, greedy_tour, nearest_neighbor_tour, utils};
+    use crate::{christofidis, greedy_tour, utils, Options};
+    use crate::delaunay::LazyDelaunay;
+    use crate::held_karp::{exact_tour, MAX_NODES};
+    use crate::metric::Metric;
 
     use std::sync::Once;
     use std::time::Instant;
 
     static INIT: Once = Once::new();
+    // up to this exact_tour is compared against trying all tours
+    const MAX_BRUTE_FORCE_NODES: usize = 9;
 
     pub fn initialize() {
         INIT.call_once(|| {
@@ -22,12 +27,13 @@ mod tests {
     fn execution_helper(test_name: &str, test_number: usize, input: &Vec<(f64, f64)>) {
         initialize();
         let graph = utils::Graph::new(input);
+        let delaunay = LazyDelaunay::new(input, Metric::Euc2d);
 
         let mut start_time = Instant::now();
         let compare = greedy_tour(&graph, true);
         let comp_duration = Instant::now() - start_time;
         start_time = Instant::now();
-        let result = christofidis(&graph, true);
+        let result = christofidis(&graph, &delaunay, &Options::default(), true, start_time);
         let duration = Instant::now() - start_time;
         assert_eq!(result.len(), input.len());
         assert!(!has_duplicates(&result));
@@ -35,6 +41,16 @@ mod tests {
         let nn_distance = calculate_distance(input, &compare);
         let result_distance = calculate_distance(input, &result);
         // assert!(greedy_distance <= nn_distance);
+        if input.len() <= MAX_NODES {
+            // small enough for the exact solver, no tour can be shorter
+            let optimum = utils::tour_length(&graph, &exact_tour(&graph));
+            assert!(result_distance as i64 >= optimum);
+            // Christofides' guarantee, the local search only makes it shorter
+            assert!(2 * result_distance as i64 <= 3 * optimum);
+            if input.len() <= MAX_BRUTE_FORCE_NODES {
+                assert_eq!(optimum, brute_force(&graph));
+            }
+        }
         info!("{:?} {:?}: Result ({:?}): {:?}; Comp ({:?}): {:?}, Diff: {:?}",test_name, test_number, duration, result_distance, comp_duration, nn_distance, result_distance - nn_distance);
     }
 
@@ -106,6 +122,30 @@ mod tests {
         points
     }
 
+    // shortest tour over all orders of the nodes after node 0
+    fn brute_force(graph: &utils::Graph) -> i64 {
+        fn extend(graph: &utils::Graph, tour: &mut Vec<i32>, used: &mut Vec<bool>, best: &mut i64) {
+            if tour.len() == used.len() {
+                *best = (*best).min(utils::tour_length(graph, tour));
+                return;
+            }
+            for x in 1..used.len() {
+                if !used[x] {
+                    used[x] = true;
+                    tour.push(x as i32);
+                    extend(graph, tour, used, best);
+                    tour.pop();
+                    used[x] = false;
+                }
+            }
+        }
+        let mut best = i64::MAX;
+        let mut used = vec![false; graph.num_nodes as usize];
+        used[0] = true;
+        extend(graph, &mut vec![0], &mut used, &mut best);
+        best
+    }
+
     fn has_duplicates(tour: &Vec<i32>) -> bool {
         let mut used = vec![false; tour.len()];
         for i in tour {
@@ -0,0 +1,260 @@
+use std::collections::VecDeque;
+
+// Array representation of a tour together with the position of every node,
+// so that successor, predecessor and "between" queries are O(1).
+#[derive(Clone)]
+pub struct Tour {
+    order: Vec<i32>,
+    pos: Vec<usize>,
+}
+
+impl Tour {
+    pub fn new(order: Vec<i32>) -> Tour {
+        let mut pos = vec![0; order.len()];
+        for (i, &x) in order.iter().enumerate() {
+            pos[x as usize] = i;
+        }
+        Tour { order, pos }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn order(&self) -> &[i32] {
+        &self.order
+    }
+
+    pub fn into_vec(self) -> Vec<i32> {
+        self.order
+    }
+
+    pub fn position(&self, node: i32) -> usize {
+        self.pos[node as usize]
+    }
+
+    pub fn next(&self, node: i32) -> i32 {
+        let i = self.pos[node as usize] + 1;
+        if i == self.order.len() { self.order[0] } else { self.order[i] }
+    }
+
+    pub fn prev(&self, node: i32) -> i32 {
+        let i = self.pos[node as usize];
+        if i == 0 { self.order[self.order.len() - 1] } else { self.order[i - 1] }
+    }
+
+    // successor when walking forward, predecessor otherwise
+    pub fn succ(&self, node: i32, forward: bool) -> i32 {
+        if forward { self.next(node) } else { self.prev(node) }
+    }
+
+    // number of steps from a to b when walking forward
+    pub fn distance(&self, a: i32, b: i32) -> usize {
+        let n = self.order.len();
+        (self.pos[b as usize] + n - self.pos[a as usize]) % n
+    }
+
+    // Reverse exactly the path from..to (walking forward), the rest of the
+    // tour keeps its orientation.
+    pub fn reverse_path(&mut self, from: i32, to: i32) {
+        let n = self.order.len();
+        let mut i = self.pos[from as usize];
+        let mut j = self.pos[to as usize];
+        // number of nodes on the path is distance + 1
+        let mut swaps = self.distance(from, to).div_ceil(2);
+        while swaps > 0 {
+            self.order.swap(i, j);
+            self.pos[self.order[i] as usize] = i;
+            self.pos[self.order[j] as usize] = j;
+            i = if i + 1 == n { 0 } else { i + 1 };
+            j = if j == 0 { n - 1 } else { j - 1 };
+            swaps -= 1;
+        }
+    }
+
+    // 2-opt move reversing the path from..to. Reverses the complementary path
+    // instead when that one is shorter, so afterwards the tour may be walked
+    // in the opposite direction; only the set of edges is guaranteed.
+    pub fn flip(&mut self, from: i32, to: i32) {
+        let inner = self.distance(from, to) + 1;
+        if 2 * inner <= self.order.len() || inner == self.order.len() {
+            self.reverse_path(from, to);
+        } else {
+            let (outer_from, outer_to) = (self.next(to), self.prev(from));
+            self.reverse_path(outer_from, outer_to);
+        }
+    }
+
+    // 2-opt move replacing the edges (t1, t2) and (t3, t4) by (t1, t3) and
+    // (t2, t4), where t2 follows t1 and t4 follows t3 in the same direction.
+    pub fn move_2opt(&mut self, t1: i32, t2: i32, t3: i32, t4: i32) {
+        debug_assert!(self.succ(t1, self.next(t1) == t2) == t2 && self.succ(t3, self.next(t1) == t2) == t4);
+        if self.next(t1) == t2 {
+            self.flip(t2, t3);
+        } else {
+            self.flip(t3, t2);
+        }
+    }
+
+    // Or-opt move: cut out the short path between u and v (given in either
+    // direction) and insert it between the adjacent nodes c and d, such that
+    // u gets connected to c and v to d.
+    pub fn move_segment(&mut self, u: i32, v: i32, c: i32, d: i32) {
+        // name everything in forward direction: p s..l nx ... c_f d_f ...
+        let (s, l) = if self.distance(u, v) <= self.distance(v, u) { (u, v) } else { (v, u) };
+        let (p, nx) = (self.prev(s), self.next(l));
+        let (c_f, d_f) = if self.next(c) == d { (c, d) } else { (d, c) };
+        self.move_2opt(p, s, c_f, d_f);
+        self.move_2opt(p, c_f, nx, l);
+        // now c_f l..s d_f; turn the segment around if s belongs next to c_f
+        if (c == c_f) == (u == s) {
+            self.move_2opt(c_f, l, s, d_f);
+        }
+    }
+
+    // Double bridge A B C D -> A C B D, where B is the path of len_b nodes
+    // starting at `from` and C the len_c nodes after it. Only the positions
+    // of B and C are rewritten, so a kick on short segments is cheap.
+    pub fn double_bridge(&mut self, from: i32, len_b: usize, len_c: usize) {
+        let n = self.order.len();
+        debug_assert!(len_b > 0 && len_c > 0 && len_b + len_c < n);
+        let start = self.pos[from as usize];
+        let mut window: Vec<i32> = (0..len_b + len_c).map(|k| self.order[(start + k) % n]).collect();
+        window.rotate_left(len_b);
+        for (k, &x) in window.iter().enumerate() {
+            let i = (start + k) % n;
+            self.order[i] = x;
+            self.pos[x as usize] = i;
+        }
+    }
+}
+
+// Queue of nodes whose don't-look bit is off. Local search only looks for
+// improving moves around queued nodes; after a move the endpoints of the
+// changed edges are queued again.
+pub struct DontLookBits {
+    queue: VecDeque<i32>,
+    queued: Vec<bool>,
+}
+
+impl DontLookBits {
+    // every node starts out queued, in tour order
+    pub fn new(tour: &Tour) -> DontLookBits {
+        DontLookBits {
+            queue: tour.order().iter().cloned().collect(),
+            queued: vec![true; tour.len()],
+        }
+    }
+
+    // all bits set, nothing queued
+    pub fn empty(num_nodes: usize) -> DontLookBits {
+        DontLookBits {
+            queue: VecDeque::new(),
+            queued: vec![false; num_nodes],
+        }
+    }
+
+    pub fn push(&mut self, node: i32) {
+        if !self.queued[node as usize] {
+            self.queued[node as usize] = true;
+            self.queue.push_back(node);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<i32> {
+        let node = self.queue.pop_front()?;
+        self.queued[node as usize] = false;
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid(tour: &Tour) -> bool {
+        (0..tour.len()).all(|i| tour.order()[tour.position(i as i32)] == i as i32)
+    }
+
+    #[test]
+    fn neighbors_and_distance() {
+        let tour = Tour::new(vec![3, 0, 4, 1, 2]);
+        assert_eq!(tour.next(2), 3);
+        assert_eq!(tour.prev(3), 2);
+        assert_eq!(tour.succ(4, false), 0);
+        assert_eq!(tour.distance(4, 2), 2);
+        assert_eq!(tour.distance(2, 4), 3);
+    }
+
+    #[test]
+    fn reverse_wrapping_path() {
+        let mut tour = Tour::new(vec![0, 1, 2, 3, 4, 5]);
+        tour.reverse_path(4, 1);
+        assert_eq!(tour.order(), &[5, 4, 2, 3, 1, 0]);
+        assert!(valid(&tour));
+    }
+
+    #[test]
+    fn flip_keeps_edges() {
+        let mut tour = Tour::new(vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        // long path, the complement 7 0 gets reversed instead
+        tour.flip(1, 6);
+        assert!(valid(&tour));
+        let edges = |t: &Tour| {
+            let mut e: Vec<(i32, i32)> = (0..8).map(|x| (x.min(t.next(x)), x.max(t.next(x)))).collect();
+            e.sort();
+            e
+        };
+        assert_eq!(edges(&tour), edges(&Tour::new(vec![0, 6, 5, 4, 3, 2, 1, 7])));
+    }
+
+    #[test]
+    fn move_2opt_either_direction() {
+        let mut tour = Tour::new(vec![0, 1, 2, 3, 4, 5]);
+        tour.move_2opt(1, 2, 4, 5);
+        assert_eq!(tour.next(1), 4);
+        assert_eq!(tour.next(2), 5);
+        let mut tour = Tour::new(vec![0, 1, 2, 3, 4, 5]);
+        tour.move_2opt(5, 4, 2, 1);
+        assert_eq!(tour.next(1), 4);
+        assert_eq!(tour.next(2), 5);
+    }
+
+    #[test]
+    fn move_segment_reversed() {
+        let mut tour = Tour::new(vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        // 2 3 between 6 and 5, 2 next to 6
+        tour.move_segment(2, 3, 6, 5);
+        assert!(valid(&tour));
+        let mut order = tour.order().to_vec();
+        let zero = order.iter().position(|&x| x == 0).unwrap();
+        order.rotate_left(zero);
+        if order[1] != 1 {
+            order[1..].reverse();
+        }
+        assert_eq!(order, vec![0, 1, 4, 5, 3, 2, 6, 7]);
+    }
+
+    #[test]
+    fn double_bridge_wrapping() {
+        let mut tour = Tour::new(vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        // B = 6 7, C = 0 1 2
+        tour.double_bridge(6, 2, 3);
+        assert_eq!(tour.order(), &[2, 6, 7, 3, 4, 5, 0, 1][..]);
+        assert!(valid(&tour));
+        assert_eq!(tour.next(5), 0);
+        assert_eq!(tour.next(2), 6);
+        assert_eq!(tour.next(7), 3);
+    }
+
+    #[test]
+    fn queue_without_duplicates() {
+        let mut bits = DontLookBits::empty(4);
+        bits.push(2);
+        bits.push(1);
+        bits.push(2);
+        assert_eq!(bits.pop(), Some(2));
+        assert_eq!(bits.pop(), Some(1));
+        assert_eq!(bits.pop(), None);
+    }
+}
@@ -0,0 +1,256 @@
+// Reading problem instances from stdin or files.
+//
+// Two layouts are supported and detected automatically:
+//  * the Kattis layout: the number of points N followed by N lines "x y"
+//  * TSPLIB .tsp files: "KEY : VALUE" header lines followed by a
+//    NODE_COORD_SECTION with lines "id x y" (ids start at 1)
+//
+// Tours are written as TSPLIB .tour files and can be read back either from
+// that format or from the Kattis output (one 0-based index per line).
+
+use crate::metric::Metric;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EdgeWeightType {
+    Euc2d,
+    Ceil2d,
+    Att,
+    Geo,
+    Man2d,
+    Max2d,
+    Other(String),
+}
+
+impl EdgeWeightType {
+    pub fn metric(&self) -> Option<Metric> {
+        match self {
+            EdgeWeightType::Euc2d => Some(Metric::Euc2d),
+            EdgeWeightType::Ceil2d => Some(Metric::Ceil2d),
+            EdgeWeightType::Att => Some(Metric::Att),
+            EdgeWeightType::Geo => Some(Metric::Geo),
+            EdgeWeightType::Man2d => Some(Metric::Man2d),
+            EdgeWeightType::Max2d => Some(Metric::Max2d),
+            EdgeWeightType::Other(_) => None,
+        }
+    }
+
+    fn from_keyword(keyword: &str) -> EdgeWeightType {
+        match keyword {
+            "EUC_2D" => EdgeWeightType::Euc2d,
+            "CEIL_2D" => EdgeWeightType::Ceil2d,
+            "ATT" => EdgeWeightType::Att,
+            "GEO" => EdgeWeightType::Geo,
+            "MAN_2D" => EdgeWeightType::Man2d,
+            "MAX_2D" => EdgeWeightType::Max2d,
+            other => EdgeWeightType::Other(other.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub name: Option<String>,
+    pub dimension: usize,
+    // the metric declared by the input; Kattis inputs are always rounded Euclidean
+    pub edge_weight_type: EdgeWeightType,
+    pub points: Vec<(f64, f64)>,
+}
+
+pub fn parse_instance(input: &str) -> Result<Instance, String> {
+    let first_token = input.split_whitespace().next();
+    match first_token {
+        None => Err("empty input".to_string()),
+        Some(token) if token.parse::<usize>().is_ok() => parse_kattis(input),
+        Some(_) => parse_tsplib(input),
+    }
+}
+
+fn parse_kattis(input: &str) -> Result<Instance, String> {
+    let mut tokens = input.split_whitespace();
+    let num: usize = tokens.next()
+        .ok_or("missing number of points")?
+        .parse().map_err(|_| "error parsing number of points")?;
+
+    let mut points = Vec::with_capacity(num);
+    for i in 0..num {
+        let x = parse_coordinate(tokens.next(), i)?;
+        let y = parse_coordinate(tokens.next(), i)?;
+        points.push((x, y));
+    }
+
+    Ok(Instance {
+        name: None,
+        dimension: num,
+        edge_weight_type: EdgeWeightType::Euc2d,
+        points,
+    })
+}
+
+fn parse_tsplib(input: &str) -> Result<Instance, String> {
+    let mut name = None;
+    let mut dimension = None;
+    let mut edge_weight_type = EdgeWeightType::Euc2d;
+    let mut lines = input.lines();
+
+    // header: everything up to the first data section
+    loop {
+        let line = match lines.next() {
+            Some(line) => line.trim(),
+            None => return Err("missing NODE_COORD_SECTION".to_string()),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => (line, ""),
+        };
+        match key {
+            "NAME" => name = Some(value.to_string()),
+            "TYPE" if value != "TSP" => return Err(format!("unsupported problem type {}", value)),
+            "DIMENSION" => {
+                dimension = Some(value.parse::<usize>().map_err(|_| format!("invalid DIMENSION {}", value))?);
+            }
+            "EDGE_WEIGHT_TYPE" => edge_weight_type = EdgeWeightType::from_keyword(value),
+            "NODE_COORD_SECTION" => break,
+            "EOF" => return Err("missing NODE_COORD_SECTION".to_string()),
+            "EDGE_WEIGHT_SECTION" => return Err("explicit edge weights are not supported".to_string()),
+            // COMMENT, NODE_COORD_TYPE, DISPLAY_DATA_TYPE, ... carry nothing we need
+            _ => {}
+        }
+    }
+
+    let dimension = dimension.ok_or("missing DIMENSION")?;
+    let mut points = vec![None; dimension];
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "EOF" || line.ends_with("_SECTION") {
+            break;
+        }
+        let mut fields = line.split_whitespace();
+        let id: usize = fields.next().unwrap()
+            .parse().map_err(|_| format!("invalid node id in line '{}'", line))?;
+        if id == 0 || id > dimension {
+            return Err(format!("node id {} out of range 1..={}", id, dimension));
+        }
+        let x = parse_coordinate(fields.next(), id - 1)?;
+        let y = parse_coordinate(fields.next(), id - 1)?;
+        points[id - 1] = Some((x, y));
+    }
+
+    let points = points.into_iter().enumerate()
+        .map(|(i, point)| point.ok_or(format!("missing coordinates for node {}", i + 1)))
+        .collect::<Result<Vec<(f64, f64)>, String>>()?;
+
+    Ok(Instance {
+        name,
+        dimension,
+        edge_weight_type,
+        points,
+    })
+}
+
+fn parse_coordinate(token: Option<&str>, node: usize) -> Result<f64, String> {
+    token.ok_or(format!("missing coordinate for node {}", node))?
+        .parse().map_err(|_| format!("invalid coordinate for node {}", node))
+}
+
+pub fn write_tour(name: Option<&str>, tour: &[i32], length: f64) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("NAME : {}.tour\n", name.unwrap_or("tsp")));
+    out.push_str(&format!("COMMENT : Length = {}\n", length));
+    out.push_str("TYPE : TOUR\n");
+    out.push_str(&format!("DIMENSION : {}\n", tour.len()));
+    out.push_str("TOUR_SECTION\n");
+    for node in tour {
+        out.push_str(&format!("{}\n", node + 1));
+    }
+    out.push_str("-1\nEOF\n");
+    out
+}
+
+// returns the 0-based tour, checked to be a permutation of the n nodes
+pub fn parse_tour(input: &str, num_nodes: usize) -> Result<Vec<i32>, String> {
+    let tour = match input.find("TOUR_SECTION") {
+        Some(start) => {
+            let mut tour = Vec::with_capacity(num_nodes);
+            for token in input[start + "TOUR_SECTION".len()..].split_whitespace() {
+                if token == "-1" || token == "EOF" {
+                    break;
+                }
+                let id: i32 = token.parse().map_err(|_| format!("invalid node id {}", token))?;
+                tour.push(id - 1);
+            }
+            tour
+        }
+        None => input.split_whitespace()
+            .map(|token| token.parse::<i32>().map_err(|_| format!("invalid node index {}", token)))
+            .collect::<Result<Vec<i32>, String>>()?,
+    };
+
+    if tour.len() != num_nodes {
+        return Err(format!("tour has {} nodes, expected {}", tour.len(), num_nodes));
+    }
+    let mut used = vec![false; num_nodes];
+    for &node in &tour {
+        if node < 0 || node as usize >= num_nodes || used[node as usize] {
+            return Err(format!("node {} is out of range or visited twice", node));
+        }
+        used[node as usize] = true;
+    }
+    Ok(tour)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kattis_format() {
+        let instance = parse_instance("3\n0 0\n1.5 2\n-3 4e2\n").unwrap();
+        assert_eq!(instance.dimension, 3);
+        assert_eq!(instance.edge_weight_type, EdgeWeightType::Euc2d);
+        assert_eq!(instance.points, vec![(0.0, 0.0), (1.5, 2.0), (-3.0, 400.0)]);
+    }
+
+    #[test]
+    fn tsplib_format() {
+        let input = "NAME : tiny\nCOMMENT : three cities\nTYPE : TSP\nDIMENSION: 3\n\
+                     EDGE_WEIGHT_TYPE : ATT\nNODE_COORD_SECTION\n2 10 20\n1 0 0\n3 5.5 6\nEOF\n";
+        let instance = parse_instance(input).unwrap();
+        assert_eq!(instance.name, Some("tiny".to_string()));
+        assert_eq!(instance.dimension, 3);
+        assert_eq!(instance.edge_weight_type, EdgeWeightType::Att);
+        assert_eq!(instance.points, vec![(0.0, 0.0), (10.0, 20.0), (5.5, 6.0)]);
+    }
+
+    #[test]
+    fn tsplib_missing_node() {
+        let input = "NAME : broken\nDIMENSION : 2\nNODE_COORD_SECTION\n1 0 0\nEOF\n";
+        assert!(parse_instance(input).is_err());
+    }
+
+    #[test]
+    fn tsplib_explicit_weights() {
+        let input = "NAME : explicit\nDIMENSION : 2\nEDGE_WEIGHT_TYPE : EXPLICIT\nEDGE_WEIGHT_SECTION\n0 1\n1 0\nEOF\n";
+        assert!(parse_instance(input).is_err());
+    }
+
+    #[test]
+    fn tour_round_trip() {
+        let tour = vec![2, 0, 3, 1];
+        let written = write_tour(Some("square"), &tour, 4.0);
+        assert!(written.starts_with("NAME : square.tour\nCOMMENT : Length = 4\n"));
+        assert_eq!(parse_tour(&written, 4).unwrap(), tour);
+    }
+
+    #[test]
+    fn kattis_tour() {
+        assert_eq!(parse_tour("1\n0\n2\n", 3).unwrap(), vec![1, 0, 2]);
+        assert!(parse_tour("1\n1\n2\n", 3).is_err());
+        assert!(parse_tour("0\n1\n", 3).is_err());
+    }
+}
@@ -1,4 +1,9 @@
 use std::time::Instant;
+use crate::distance::DistanceOracle;
+use crate::metric::Metric;
+use crate::neighbors::CandidateList;
+use crate::sparse_graph::SparseGraph;
+use crate::tour::{DontLookBits, Tour};
 
 pub struct Graph {
     pub num_nodes: i32,
@@ -9,13 +14,18 @@ pub struct Graph {
 }
 
 impl Graph {
+    #[cfg(test)]
     pub(crate) fn new(points: &Vec<(f64, f64)>) -> Graph {
+        Graph::with_metric(points, Metric::Euc2d)
+    }
+
+    pub(crate) fn with_metric(points: &[(f64, f64)], metric: Metric) -> Graph {
         let num_nodes: i32 = points.len() as i32;
         let mut edges = Vec::new();
         for i in 0..(num_nodes - 1) as usize {
             edges.push(vec![0; (num_nodes - (i as i32 + 1)) as usize]);
             for j in i+1..num_nodes as usize {
-                edges[i][j-(i+1)] = euclidean_distance(points[i], points[j]);
+                edges[i][j-(i+1)] = metric.distance(points[i], points[j]);
             }
         }
         Graph {
@@ -34,250 +44,408 @@ impl Graph {
         self.edges[x as usize][(y-(x+1)) as usize]
     }
 
+    #[cfg(test)]
     pub(crate) fn get_edges_sorted(&self) -> Vec<(i32, i32)> {
-        let mut edges_vec: Vec<(i32, i32)> = Vec::new();
+        // sorting on the stored weight avoids two get_edge lookups per comparison
+        let mut edges_vec: Vec<(i32, i32, i32)> = Vec::new();
         for i in 0..self.num_nodes {
             for j in i+1..self.num_nodes {
-                edges_vec.push((i, j));
-            }
-        }
-        edges_vec.sort_by(|a, b| self.get_edge(a.0, a.1).cmp(&self.get_edge(b.0, b.1)));
-        edges_vec.reverse();
-        edges_vec
-    }
-
-    // Kruskal's algorithm
-    pub(crate) fn get_min_spanning_tree(&self) -> SparseGraph {
-        let mut sorted = self.get_edges_sorted();
-        let mut sparse_graph = SparseGraph::new(self.num_nodes);
-
-        let mut edge_count = 0;
-        while !sorted.is_empty() && edge_count < self.num_nodes - 1 {
-            let (x, y) = sorted.pop().unwrap();
-            sparse_graph.add_edge(x, y);
-            edge_count += 1;
-            let circle_length = sparse_graph.get_circle(x);
-            if circle_length >= 0 {
-                sparse_graph.remove_edge(x, y);
-                edge_count -= 1;
+                edges_vec.push((self.get_edge(i, j), i, j));
             }
         }
-        sparse_graph
+        edges_vec.sort_unstable_by(|a, b| b.cmp(a));
+        edges_vec.into_iter().map(|(_, i, j)| (i, j)).collect()
+    }
+}
+
+impl DistanceOracle for Graph {
+    fn num_nodes(&self) -> usize {
+        self.num_nodes as usize
+    }
+
+    fn get_edge(&self, x: i32, y: i32) -> i32 {
+        Graph::get_edge(self, x, y)
     }
 }
 
-pub struct SparseGraph {
-    num_nodes: i32,
-    pub(crate) adjacency_list: Vec<Vec<i32>>
+// below this many edges Kruskal just sorts them
+const KRUSKAL_SORT_EDGES: usize = 64;
+
+// Kruskal's algorithm on (length, x, y) edges in any order. Instead of
+// sorting all of them first, they are split at the median like in
+// quicksort: the shorter half goes first, then the longer half without the
+// edges that would close a cycle by then. On a complete graph most edges
+// are dropped that way long before they would have been sorted.
+pub fn kruskal(num_nodes: usize, edges: &mut [(i32, i32, i32)]) -> SparseGraph {
+    let mut sparse_graph = SparseGraph::new(num_nodes as i32);
+    let mut components = DisjointSet::new(num_nodes);
+    let mut missing = num_nodes.saturating_sub(1);
+    filter_kruskal(edges, &mut components, &mut sparse_graph, &mut missing);
+    sparse_graph
 }
 
-impl SparseGraph {
-    pub(crate) fn new(num_nodes: i32) -> SparseGraph {
-        SparseGraph {
-            num_nodes,
-            adjacency_list: vec![Vec::new(); num_nodes as usize]
+fn filter_kruskal(edges: &mut [(i32, i32, i32)], components: &mut DisjointSet, sparse_graph: &mut SparseGraph, missing: &mut usize) {
+    if *missing == 0 {
+        return;
+    }
+    if edges.len() <= KRUSKAL_SORT_EDGES {
+        edges.sort_unstable();
+        for &(_, x, y) in edges.iter() {
+            // an edge inside one component would close a cycle
+            if *missing > 0 && components.union(x, y) {
+                sparse_graph.add_edge(x, y);
+                *missing -= 1;
+            }
+        }
+        return;
+    }
+    let mid = edges.len() / 2;
+    edges.select_nth_unstable(mid);
+    let (shorter, longer) = edges.split_at_mut(mid);
+    filter_kruskal(shorter, components, sparse_graph, missing);
+    let mut kept = 0;
+    for i in 0..longer.len() {
+        let (_, x, y) = longer[i];
+        if components.find(x) != components.find(y) {
+            longer.swap(kept, i);
+            kept += 1;
         }
     }
+    // the median itself is the shortest of them and stays in front
+    filter_kruskal(&mut longer[..kept], components, sparse_graph, missing);
+}
+
+#[cfg(test)]
+pub fn euclidean_distance(a: (f64, f64), b: (f64, f64)) -> i32 {
+    Metric::Euc2d.distance(a, b)
+}
 
-    pub(crate) fn add_edge(&mut self, x: i32, y: i32) {
-        self.adjacency_list[x as usize].push(y);
-        self.adjacency_list[y as usize].push(x);
+// i64 because the total of large coordinate instances overflows i32
+pub fn tour_length<D: DistanceOracle>(graph: &D, tour: &[i32]) -> i64 {
+    if tour.len() < 2 {
+        return 0;
+    }
+    let mut length = graph.get_edge(tour[tour.len() - 1], tour[0]) as i64;
+    for i in 0..tour.len() - 1 {
+        length += graph.get_edge(tour[i], tour[i + 1]) as i64;
     }
+    length
+}
 
-    pub(crate) fn remove_edge(&mut self, x: i32, y:i32) {
-        let index = self.adjacency_list[x as usize].iter().position(|&r| r == y).unwrap();
-        self.adjacency_list[x as usize].swap_remove(index);
-        let index = self.adjacency_list[y as usize].iter().position(|&r| r == x).unwrap();
-        self.adjacency_list[y as usize].swap_remove(index);
+pub fn two_opt<D: DistanceOracle>(graph: &D, candidates: &CandidateList, tour:Vec<i32>, start_time:Instant, max_processing_ms: u128) -> Vec<i32> {
+    if tour.len() < 4 {
+        return tour;
+    }
+    let mut tour = Tour::new(tour);
+    let mut queue = DontLookBits::new(&tour);
+    while start_time.elapsed().as_millis() < max_processing_ms {
+        match queue.pop() {
+            Some(a) => two_opt_node(graph, candidates, &mut tour, &mut queue, a),
+            None => break,
+        };
     }
+    tour.into_vec()
+}
 
-    pub(crate) fn get_circle(&self, start_vertex: i32) -> i32 {
-        let mut visited = vec![-1; self.num_nodes as usize];
-        let mut stack = Vec::new();
-        stack.push((start_vertex,start_vertex,0));
-        while !stack.is_empty() {
-            let (node, parent, index) = stack.pop().unwrap();
-            // return circle length if node was already visited
-            if visited[node as usize] >= 0 {
-                return index - visited[node as usize];
+// Apply the best 2-opt move that removes an edge at a and adds an edge from
+// a to one of its candidates. Returns whether the tour changed.
+pub fn two_opt_node<D: DistanceOracle>(graph: &D, candidates: &CandidateList, tour: &mut Tour, queue: &mut DontLookBits, a: i32) -> bool {
+    // (gain, direction, b)
+    let mut best = (0, true, 0);
+    for forward in [true, false] {
+        let a_succ = tour.succ(a, forward);
+        let old_a = graph.get_edge(a, a_succ);
+        for &b in candidates.get(a) {
+            let new_1 = graph.get_edge(a, b);
+            if new_1 >= old_a {
+                break;
             }
-            visited[node as usize] = index;
-            // push the neighbors of the current node to the stack
-            if self.adjacency_list[node as usize].len() != 0 {
-                for neighbor in &self.adjacency_list[node as usize] {
-                    if *neighbor == parent {
-                        continue;
-                    } else {
-                        stack.push((*neighbor, node, index + 1));
-                    }
-                }
+            let b_succ = tour.succ(b, forward);
+            if b == a_succ || b_succ == a {
+                continue;
+            }
+            let gain = old_a + graph.get_edge(b, b_succ) - new_1 - graph.get_edge(a_succ, b_succ);
+            if gain > best.0 {
+                best = (gain, forward, b);
             }
-
-            // if the stack gets empty check if there is a node that has not been visited
-            /*if stack.is_empty() && visited.iter().any(|&x| x == -1) {
-                let unvisited = visited.iter().position(|&x| x == -1).unwrap() as i32;
-                stack.push((unvisited, unvisited, 0));
-            }*/
-
         }
-        -1
     }
 
-    pub(crate) fn get_vertex_degree(&self, x: i32) -> i32 {
-        self.adjacency_list[x as usize].len() as i32
+    let (gain, forward, b) = best;
+    if gain <= 0 {
+        return false;
     }
-
-    pub(crate) fn get_neighbors(&self, x: i32) -> Vec<i32> {
-        self.adjacency_list[x as usize].clone()
+    let a_succ = tour.succ(a, forward);
+    let b_succ = tour.succ(b, forward);
+    if forward {
+        tour.flip(a_succ, b);
+    } else {
+        tour.flip(b, a_succ);
     }
-
-    pub(crate) fn get_edge(&self) -> Option<(i32, i32)> {
-        for i in 0..self.num_nodes as usize {
-            if self.adjacency_list[i].len() != 0 {
-                return Some((i as i32, self.adjacency_list[i][0]));
-            }
-        }
-        None
+    for x in [a, a_succ, b, b_succ] {
+        queue.push(x);
     }
+    true
 }
 
-pub fn euclidean_distance((x1, y1): (f64, f64), (x2, y2): (f64, f64)) -> i32 {
-        ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt().round() as i32
+pub fn three_opt<D: DistanceOracle>(graph: &D, candidates: &CandidateList, tour:Vec<i32>, start_time:Instant, max_processing_ms: u128) -> Vec<i32> {
+    if tour.len() < 5 {
+        return two_opt(graph, candidates, tour, start_time, max_processing_ms);
+    }
+    let mut tour = Tour::new(tour);
+    let mut queue = DontLookBits::new(&tour);
+    while start_time.elapsed().as_millis() < max_processing_ms {
+        match queue.pop() {
+            Some(a) => three_opt_node(graph, candidates, &mut tour, &mut queue, a),
+            None => break,
+        };
+    }
+    tour.into_vec()
 }
 
-pub fn two_opt(graph: &Graph, mut tour:Vec<i32>, start_time:Instant, max_processing_ms: u128) -> Vec<i32> {
-    let mut improved = true;
-
-    while improved &&  start_time.elapsed().as_millis() < max_processing_ms {
-        improved = false;
-        for i in 0..tour.len() - 1 {
-            for j in i + 2..tour.len() - 1 {
-                if j != i && j != i + 1 {
-
-                    let old_1 = graph.get_edge(tour[i], tour[i+1]);
-                    let old_2 = graph.get_edge(tour[j], tour[(j + 1) % tour.len()]);
-                    let old_dist = old_1 + old_2;
+// Apply the best 3-opt move around a, walking the tour in both directions.
+//
+// The edges (a, a_succ), (b, b_succ) and (c, c_succ) are removed, with the
+// nodes in that order along the walk. With s1 = a_succ..b and s2 = b_succ..c
+// the part between a and c_succ becomes one of
+//   0: rev(s1)            (2-opt, c is unused)
+//   1: s2 s1              (or2opt, segment moved without reversal)
+//   2: s2 rev(s1)
+//   3: rev(s2) s1
+//   4: rev(s1) rev(s2)
+// The first new edge always connects a to one of its candidates, the
+// second one connects a_succ or b to one of theirs.
+pub fn three_opt_node<D: DistanceOracle>(graph: &D, candidates: &CandidateList, tour: &mut Tour, queue: &mut DontLookBits, a: i32) -> bool {
+    // (gain, direction, move type, b, c)
+    let mut best = (0, true, 0, 0, 0);
+    for forward in [true, false] {
+        let a_succ = tour.succ(a, forward);
+        let old_a = graph.get_edge(a, a_succ);
+        // steps from a to x in walking direction
+        let rel = |x: i32| if forward { tour.distance(a, x) } else { tour.distance(x, a) };
+
+        for &x in candidates.get(a) {
+            let new_1 = graph.get_edge(a, x);
+            let g1 = old_a - new_1;
+            if g1 <= 0 {
+                break;
+            }
+            let rel_x = rel(x);
+            if rel_x < 2 {
+                continue;
+            }
+            let x_succ = tour.succ(x, forward);
+            let x_pred = tour.succ(x, !forward);
+
+            // x = b_succ: types 1 and 2
+            let b = x_pred;
+            let g2 = g1 + graph.get_edge(b, x);
+            for &c in candidates.get(a_succ) {
+                if rel(c) < rel_x {
+                    continue;
+                }
+                let c_succ = tour.succ(c, forward);
+                let gain = g2 + graph.get_edge(c, c_succ) - graph.get_edge(c, a_succ) - graph.get_edge(b, c_succ);
+                if gain > best.0 {
+                    best = (gain, forward, 1, b, c);
+                }
+            }
+            for &c in candidates.get(b) {
+                if rel(c) < rel_x {
+                    continue;
+                }
+                let c_succ = tour.succ(c, forward);
+                let gain = g2 + graph.get_edge(c, c_succ) - graph.get_edge(c, b) - graph.get_edge(a_succ, c_succ);
+                if gain > best.0 {
+                    best = (gain, forward, 2, b, c);
+                }
+            }
 
-                    let new_1 = graph.get_edge(tour[i], tour[j]);
-                    let new_2 = graph.get_edge(tour[i+1], tour[(j + 1) % tour.len()]);
-                    let new_dist = new_1 + new_2;
-                    // tour[i + 1..=j].reverse();
+            // x = c: type 3
+            let g2 = g1 + graph.get_edge(x, x_succ);
+            for &b_succ in candidates.get(a_succ) {
+                let rel_b_succ = rel(b_succ);
+                if rel_b_succ < 2 || rel_b_succ > rel_x {
+                    continue;
+                }
+                let b = tour.succ(b_succ, !forward);
+                let gain = g2 + graph.get_edge(b, b_succ) - graph.get_edge(b_succ, a_succ) - graph.get_edge(b, x_succ);
+                if gain > best.0 {
+                    best = (gain, forward, 3, b, x);
+                }
+            }
 
-                    if new_dist < old_dist {
-                        tour[i + 1..=j].reverse();
-                        improved = true;
-                    }
+            // x = b: 2-opt and type 4
+            let gain = g2 - graph.get_edge(a_succ, x_succ);
+            if gain > best.0 {
+                best = (gain, forward, 0, x, x);
+            }
+            for &c in candidates.get(a_succ) {
+                if rel(c) <= rel_x {
+                    continue;
+                }
+                let c_succ = tour.succ(c, forward);
+                let gain = g2 + graph.get_edge(c, c_succ) - graph.get_edge(a_succ, c) - graph.get_edge(x_succ, c_succ);
+                if gain > best.0 {
+                    best = (gain, forward, 4, x, c);
                 }
             }
         }
     }
 
-    tour
+    let (gain, forward, move_type, b, c) = best;
+    if gain <= 0 {
+        return false;
+    }
+    let a_succ = tour.succ(a, forward);
+    let b_succ = tour.succ(b, forward);
+    let c_succ = tour.succ(c, forward);
+    // reverse the walk from x to y
+    let mut reverse = |x: i32, y: i32| if forward { tour.reverse_path(x, y) } else { tour.reverse_path(y, x) };
+    match move_type {
+        0 => reverse(a_succ, b),
+        1 => {
+            reverse(a_succ, b);
+            reverse(b_succ, c);
+            reverse(b, b_succ);
+        }
+        2 => {
+            reverse(b_succ, c);
+            reverse(a_succ, b_succ);
+        }
+        3 => {
+            reverse(a_succ, b);
+            reverse(b, c);
+        }
+        4 => {
+            reverse(a_succ, b);
+            reverse(b_succ, c);
+        }
+        _ => panic!("Invalid move")
+    }
+    for x in [a, a_succ, b, b_succ, c, c_succ] {
+        queue.push(x);
+    }
+    true
 }
 
-pub fn three_opt(graph: &Graph, mut tour:Vec<i32>, start_time:Instant, max_processing_ms: u128) -> Vec<i32> {
-    let mut improved = true;
-
-    while improved &&  start_time.elapsed().as_millis() < max_processing_ms {
-        improved = false;
-        for i in 0..tour.len() - 1 {
-            for j in i + 2..tour.len() - 1 {
-                for k in j + 2..tour.len() - 1 {
-                    if j != i && j != i + 1 && k != i && k != i + 1 && k != j && k != j + 1 {
-
-                        let old_1 = graph.get_edge(tour[i], tour[i+1]);
-                        let old_2 = graph.get_edge(tour[j], tour[(j + 1) % tour.len()]);
-                        let old_3 = graph.get_edge(tour[k], tour[(k + 1) % tour.len()]);
-                        let old_dist = old_1 + old_2 + old_3;
-
-                        let mut best_dist = old_dist;
-                        let mut flip:Vec<i32> = Vec::new();
-
-                        // one-flip options
-                        let new_1 = graph.get_edge(tour[i], tour[j]);
-                        let new_2 = graph.get_edge(tour[i+1], tour[(j + 1) % tour.len()]);
-                        let new_3 = graph.get_edge(tour[k], tour[(k + 1) % tour.len()]);
-                        if (new_1 + new_2 + new_3) < best_dist {
-                            best_dist = new_1 + new_2 + new_3;
-                            flip = vec![0];
-                        }
-
-                        let new_1 = graph.get_edge(tour[i], tour[i+1]);
-                        let new_2 = graph.get_edge(tour[j], tour[k]);
-                        let new_3 = graph.get_edge(tour[(j + 1) % tour.len()], tour[(k + 1) % tour.len()]);
-                        if (new_1 + new_2 + new_3) < best_dist {
-                            best_dist = new_1 + new_2 + new_3;
-                            flip = vec![1];
-                        }
-
-                        let new_1 = graph.get_edge(tour[i], tour[k]);
-                        let new_2 = graph.get_edge(tour[(i + 1) % tour.len()], tour[(k + 1) % tour.len()]);
-                        let new_3 = graph.get_edge(tour[j], tour[(j + 1) % tour.len()]);
-                        if (new_1 + new_2 + new_3) < best_dist {
-                            best_dist = new_1 + new_2 + new_3;
-                            flip = vec![2];
-                        }
+// longest segment Or-opt moves
+const OR_OPT_SEGMENT: usize = 3;
 
-                        // two-flip options
-                        let new_1 = graph.get_edge(tour[i], tour[(j+1) % tour.len()]);
-                        let new_2 = graph.get_edge(tour[(i + 1) % tour.len()], tour[(k + 1) % tour.len()]);
-                        let new_3 = graph.get_edge(tour[k], tour[j]);
-                        if (new_1 + new_2 + new_3) < best_dist {
-                            best_dist = new_1 + new_2 + new_3;
-                            flip = vec![1, 2];
-                        }
+pub fn or_opt<D: DistanceOracle>(graph: &D, candidates: &CandidateList, tour:Vec<i32>, start_time:Instant, max_processing_ms: u128) -> Vec<i32> {
+    if tour.len() < OR_OPT_SEGMENT + 3 {
+        return tour;
+    }
+    let mut tour = Tour::new(tour);
+    let mut queue = DontLookBits::new(&tour);
+    while start_time.elapsed().as_millis() < max_processing_ms {
+        match queue.pop() {
+            Some(a) => or_opt_node(graph, candidates, &mut tour, &mut queue, a),
+            None => break,
+        };
+    }
+    tour.into_vec()
+}
 
-                        let new_1 = graph.get_edge(tour[i], tour[k]);
-                        let new_2 = graph.get_edge(tour[(i + 1) % tour.len()], tour[(j+1) % tour.len()]);
-                        let new_3 = graph.get_edge(tour[j], tour[(k + 1) % tour.len()]);
-                        if (new_1 + new_2 + new_3) < best_dist {
-                            best_dist = new_1 + new_2 + new_3;
-                            flip = vec![0, 2];
-                        }
+// Apply the best move that cuts out a segment of 1 to 3 cities starting at
+// a and inserts it, possibly reversed, between two adjacent cities c and d,
+// where c is a candidate of one of the segment's ends.
+pub fn or_opt_node<D: DistanceOracle>(graph: &D, candidates: &CandidateList, tour: &mut Tour, queue: &mut DontLookBits, a: i32) -> bool {
+    // (gain, u, v, c, d): u..v is the segment, u gets connected to c and v to d
+    let mut best = (0, 0, 0, 0, 0);
+    for forward in [true, false] {
+        let u = a;
+        let pu = tour.succ(u, !forward);
+        let mut v = u;
+        for length in 1..=OR_OPT_SEGMENT {
+            if length > 1 {
+                v = tour.succ(v, forward);
+            }
+            let pv = tour.succ(v, forward);
+            let in_segment = |x: i32| {
+                let steps = if forward { tour.distance(u, x) } else { tour.distance(x, u) };
+                steps < length
+            };
+            let removal_gain = graph.get_edge(pu, u) + graph.get_edge(v, pv) - graph.get_edge(pu, pv);
+            if removal_gain <= 0 {
+                continue;
+            }
 
-                        let new_1 = graph.get_edge(tour[i], tour[j]);
-                        let new_2 = graph.get_edge(tour[(i + 1) % tour.len()], tour[k]);
-                        let new_3 = graph.get_edge(tour[(j + 1) % tour.len()], tour[(k + 1) % tour.len()]);
-                        if (new_1 + new_2 + new_3) < best_dist {
-                            best_dist = new_1 + new_2 + new_3;
-                            flip = vec![0, 1];
+            for (end, other) in [(u, v), (v, u)] {
+                for &c in candidates.get(end) {
+                    let new_1 = graph.get_edge(end, c);
+                    if new_1 >= removal_gain {
+                        break;
+                    }
+                    if in_segment(c) {
+                        continue;
+                    }
+                    for d in [tour.next(c), tour.prev(c)] {
+                        if in_segment(d) {
+                            continue;
                         }
-
-                        // three-flip options
-                        let new_1 = graph.get_edge(tour[i], tour[(j+1) % tour.len()]);
-                        let new_2 = graph.get_edge(tour[(i + 1) % tour.len()], tour[k]);
-                        let new_3 = graph.get_edge(tour[j], tour[(k + 1) % tour.len()]);
-                        if (new_1 + new_2 + new_3) < best_dist {
-                            best_dist = new_1 + new_2 + new_3;
-                            flip = vec![0, 1, 2];
+                        let gain = removal_gain + graph.get_edge(c, d) - new_1 - graph.get_edge(other, d);
+                        if gain > best.0 {
+                            best = if end == u { (gain, u, v, c, d) } else { (gain, v, u, c, d) };
                         }
+                    }
+                }
+            }
+        }
+    }
 
+    let (gain, u, v, c, d) = best;
+    if gain <= 0 {
+        return false;
+    }
+    // the outer neighbors of the segment are among these
+    for x in [tour.prev(u), tour.next(u), tour.prev(v), tour.next(v)] {
+        queue.push(x);
+    }
+    tour.move_segment(u, v, c, d);
+    for x in [u, v, c, d] {
+        queue.push(x);
+    }
+    true
+}
 
-                        // choose best and apply
-                        if (best_dist <old_dist) {
-                            for f in flip {
-                                match f {
-                                    0 => tour[i + 1..=j].reverse(),
-                                    1 => tour[j + 1..=k].reverse(),
-                                    2 => tour[i + 1..=k].reverse(),
-                                    _ => panic!("Invalid flip")
-                                }
-                            }
-                            improved = true;
-                        }
-
-                        if (start_time.elapsed().as_millis() >= max_processing_ms) {
-                            return tour;
-                        }
+// Union-find with union by size and path halving, so that checking whether
+// an edge would close a cycle is near-constant time.
+pub struct DisjointSet {
+    parent: Vec<i32>,
+    size: Vec<i32>,
+}
 
+impl DisjointSet {
+    pub fn new(num_nodes: usize) -> DisjointSet {
+        DisjointSet {
+            parent: (0..num_nodes as i32).collect(),
+            size: vec![1; num_nodes],
+        }
+    }
 
-                    }
-                }
-            }
+    pub fn find(&mut self, mut x: i32) -> i32 {
+        while self.parent[x as usize] != x {
+            let grandparent = self.parent[self.parent[x as usize] as usize];
+            self.parent[x as usize] = grandparent;
+            x = grandparent;
         }
+        x
     }
 
-    tour
+    // merges the sets of x and y, false if they already were the same set
+    pub fn union(&mut self, x: i32, y: i32) -> bool {
+        let (mut x, mut y) = (self.find(x), self.find(y));
+        if x == y {
+            return false;
+        }
+        if self.size[x as usize] < self.size[y as usize] {
+            std::mem::swap(&mut x, &mut y);
+        }
+        self.parent[y as usize] = x;
+        self.size[x as usize] += self.size[y as usize];
+        true
+    }
 }
 
 pub struct SimpleRng {
@@ -298,8 +466,10 @@ impl SimpleRng {
         self.state as u32
     }
 
+    // the low bits of a power-of-two LCG have short periods, so scale the
+    // whole value instead of taking it modulo the range
     pub fn gen_range(&mut self, start: usize, end: usize) -> usize {
-        start + (self.next_u32() as usize) % (end - start)
+        start + ((self.next_u32() as u64 * (end - start) as u64) >> 32) as usize
     }
 
     pub fn shuffle<T>(&mut self, slice: &mut [T]) {
@@ -312,3 +482,55 @@ impl SimpleRng {
         self.next_u32() as f64 / u32::MAX as f64
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disjoint_set_unions() {
+        let mut sets = DisjointSet::new(5);
+        assert!(sets.union(0, 1));
+        assert!(sets.union(3, 4));
+        assert!(!sets.union(1, 0));
+        assert!(sets.union(1, 4));
+        assert_eq!(sets.find(0), sets.find(3));
+        assert_ne!(sets.find(2), sets.find(4));
+    }
+
+    #[test]
+    fn spanning_tree_weight() {
+        let mut rng = SimpleRng::new(9);
+        let points: Vec<(f64, f64)> = (0..60).map(|_| (rng.next_f64() * 100.0, rng.next_f64() * 100.0)).collect();
+        let graph = Graph::with_metric(&points, Metric::Euc2d);
+        let mut edges: Vec<(i32, i32, i32)> = (0..60).flat_map(|x| (x + 1..60).map(move |y| (x, y)))
+            .map(|(x, y)| (graph.get_edge(x, y), x, y))
+            .collect();
+        let tree = kruskal(60, &mut edges);
+        let mut weight = 0;
+        for x in 0..60 {
+            for &y in &tree.adjacency_list[x as usize] {
+                if x < y {
+                    weight += graph.get_edge(x, y);
+                }
+            }
+        }
+
+        // Prim for comparison
+        let mut in_tree = [false; 60];
+        let mut dist = [i32::MAX; 60];
+        dist[0] = 0;
+        let mut expected = 0;
+        for _ in 0..60 {
+            let x = (0..60).filter(|&x| !in_tree[x]).min_by_key(|&x| dist[x]).unwrap();
+            in_tree[x] = true;
+            expected += dist[x];
+            for y in 0..60 {
+                if !in_tree[y] {
+                    dist[y] = dist[y].min(graph.get_edge(x as i32, y as i32));
+                }
+            }
+        }
+        assert_eq!(weight, expected);
+    }
+}